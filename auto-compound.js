@@ -9,6 +9,10 @@ const fs = require('fs');
 const path = require('path');
 
 const PROGRAM_ID = new PublicKey('t6YG88Q2wCsimhQ5gqSeRC8Wm5qVksw62urHAezPGPU');
+const TOKEN_PROGRAM_ID = new PublicKey('TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA');
+const ASSOCIATED_TOKEN_PROGRAM_ID = new PublicKey('ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL');
+// Byte offset of GlobalState.gpu_token_mint (after the 8-byte discriminator)
+const GPU_TOKEN_MINT_OFFSET = 125;
 const RPC_URL = process.env.RPC_URL || 'https://api.mainnet-beta.solana.com';
 const COMPOUND_INTERVAL_MS = 60 * 1000; // 60 seconds
 const MIN_HASH_TO_COMPOUND = 86400; // Minimum hash needed
@@ -40,12 +44,35 @@ async function autoCompound() {
       PROGRAM_ID
     );
 
+    // Vaults are needed so the program can release pool rewards before compounding
+    const [solVaultPda] = PublicKey.findProgramAddressSync(
+      [Buffer.from('vault')],
+      PROGRAM_ID
+    );
+
+    const [gpuVaultAuthority] = PublicKey.findProgramAddressSync(
+      [Buffer.from('gpu_vault')],
+      PROGRAM_ID
+    );
+
+    const globalStateInfo = await connection.getAccountInfo(globalStatePda);
+    const gpuTokenMint = new PublicKey(
+      globalStateInfo.data.subarray(GPU_TOKEN_MINT_OFFSET, GPU_TOKEN_MINT_OFFSET + 32)
+    );
+
+    const [gpuVault] = PublicKey.findProgramAddressSync(
+      [gpuVaultAuthority.toBuffer(), TOKEN_PROGRAM_ID.toBuffer(), gpuTokenMint.toBuffer()],
+      ASSOCIATED_TOKEN_PROGRAM_ID
+    );
+
+    const vaults = { solVaultPda, gpuVault, gpuVaultAuthority };
+
     // Immediate first check
-    await performCompound(connection, keypair, globalStatePda, userStatePda);
+    await performCompound(connection, keypair, globalStatePda, userStatePda, vaults);
 
     // Schedule periodic compounds
     setInterval(async () => {
-      await performCompound(connection, keypair, globalStatePda, userStatePda);
+      await performCompound(connection, keypair, globalStatePda, userStatePda, vaults);
     }, COMPOUND_INTERVAL_MS);
 
   } catch (error) {
@@ -54,7 +81,7 @@ async function autoCompound() {
   }
 }
 
-async function performCompound(connection, keypair, globalStatePda, userStatePda) {
+async function performCompound(connection, keypair, globalStatePda, userStatePda, vaults) {
   try {
     console.log(`\n[${new Date().toISOString()}] Attempting compound...`);
 
@@ -67,6 +94,9 @@ async function performCompound(connection, keypair, globalStatePda, userStatePda
         { pubkey: globalStatePda, isSigner: false, isWritable: true },
         { pubkey: userStatePda, isSigner: false, isWritable: true },
         { pubkey: keypair.publicKey, isSigner: true, isWritable: true },
+        { pubkey: vaults.solVaultPda, isSigner: false, isWritable: false },
+        { pubkey: vaults.gpuVault, isSigner: false, isWritable: false },
+        { pubkey: vaults.gpuVaultAuthority, isSigner: false, isWritable: false },
       ],
       programId: PROGRAM_ID,
       data: discriminator,
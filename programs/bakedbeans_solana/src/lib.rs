@@ -1,21 +1,215 @@
+
+// Anchor 0.31's generated IDL handlers still call the deprecated AccountInfo::realloc.
+// They are emitted at the crate root, outside any item we could annotate, so the
+// allow is limited to the builds that contain them.
+#![cfg_attr(not(feature = "no-idl"), allow(deprecated))]
+
 use anchor_lang::prelude::*;
 use anchor_spl::token::{self, Token, Transfer as SplTransfer};
-use anchor_spl::token_interface::TokenAccount;
+use anchor_spl::associated_token::{get_associated_token_address, AssociatedToken};
+use anchor_spl::token_interface::{Mint, TokenAccount};
 
 declare_id!("t6YG88Q2wCsimhQ5gqSeRC8Wm5qVksw62urHAezPGPU");
 
 // GPU Token Decimals (constant since most tokens use 6 or 9)
 pub const GPU_TOKEN_DECIMALS: u8 = 6;
 
+// Hash needed to compound into 1 MH/s (one day of output), the minimum difficulty
+pub const HASH_PER_MHS: u64 = 86_400;
+
+// Difficulty retargets once per epoch (1 day by default) towards 5% hashrate growth
+// per epoch, moving at most 4x either way per retarget
+pub const DEFAULT_DIFFICULTY_EPOCH_SECS: u32 = 24 * 60 * 60;
+pub const DEFAULT_TARGET_GROWTH_BPS: u16 = 500;
+#[constant]
+pub const MIN_DIFFICULTY_EPOCH_SECS: u32 = 60 * 60;
+#[constant]
+pub const MAX_DIFFICULTY_EPOCH_SECS: u32 = 30 * 24 * 60 * 60;
+#[constant]
+pub const MAX_TARGET_GROWTH_BPS: u16 = 10_000;
+#[constant]
+pub const MAX_DIFFICULTY_ADJUSTMENT: u64 = 4;
+
+// Pyth pull-oracle receiver program, owner of PriceUpdateV2 accounts
+pub const PYTH_RECEIVER_PROGRAM_ID: Pubkey = pubkey!("rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ");
+
+// sha256("account:PriceUpdateV2")[..8]
+pub const PYTH_PRICE_UPDATE_DISCRIMINATOR: [u8; 8] = [34, 241, 35, 99, 157, 126, 244, 205];
+
+// Oracle guard defaults: prices up to 60s old, confidence within 2% of price
+pub const DEFAULT_MAX_PRICE_AGE_SECS: u32 = 60;
+pub const DEFAULT_MAX_CONFIDENCE_BPS: u16 = 200;
+
+// Manual prices go stale a day after the last update_prices
+pub const DEFAULT_MANUAL_PRICE_MAX_AGE_SECS: u32 = 24 * 60 * 60;
+
+// Manual price updates may move each price by at most 20% without an override
+pub const DEFAULT_MAX_PRICE_MOVE_BPS: u16 = 2_000;
+// Routine price updates move at most max_price_move_bps from the prices at the start of this window
+pub const PRICE_MOVE_WINDOW_SECS: i64 = 86_400;
+
+// Fixed-point scale for the reward-per-MH/s indices
+pub const ACC_PRECISION: u128 = 1_000_000_000_000;
+
+// Referral schedule: at most 5 upline levels paying at most 20% of a buy in total.
+// Until a SetReferralSchedule proposal runs, only the direct referrer earns the legacy 5%.
+pub const MAX_REFERRAL_LEVELS: usize = 5;
+pub const MAX_REFERRAL_TOTAL_BPS: u16 = 2_000;
+pub const DEFAULT_REFERRAL_BPS: u16 = 500;
+
+// Lock terms and their reward-weight multipliers (bps, 10,000 = 1x)
+#[constant]
+pub const LOCK_30D_MULTIPLIER_BPS: u16 = 11_000;
+#[constant]
+pub const LOCK_90D_MULTIPLIER_BPS: u16 = 12_500;
+#[constant]
+pub const LOCK_180D_MULTIPLIER_BPS: u16 = 15_000;
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+// Fee split table holds at most 6 recipients
+pub const MAX_FEE_RECIPIENTS: usize = 6;
+
+// UpdateConfig bounds: daily pool 1-20% of TVL, protocol fee up to 20%,
+// GPU penalty up to 50%, base rate 1 to 1M MH/s per SOL
+#[constant]
+pub const MIN_DAILY_POOL_PERCENTAGE: u8 = 1;
+#[constant]
+pub const MAX_DAILY_POOL_PERCENTAGE: u8 = 20;
+#[constant]
+pub const MAX_PROTOCOL_FEE_VAL: u8 = 20;
+#[constant]
+pub const MAX_GPU_PENALTY_BPS: u16 = 5_000;
+#[constant]
+pub const MIN_BASE_BUY_RATE: u64 = 1;
+#[constant]
+pub const MAX_BASE_BUY_RATE: u64 = 1_000_000;
+
+// Pause bits in GlobalState.paused, each stopping one user action
+#[constant]
+pub const PAUSE_SOL_BUYS: u8 = 1 << 0;
+#[constant]
+pub const PAUSE_GPU_BUYS: u8 = 1 << 1;
+#[constant]
+pub const PAUSE_COMPOUND: u8 = 1 << 2;
+#[constant]
+pub const PAUSE_SOL_CLAIMS: u8 = 1 << 3;
+#[constant]
+pub const PAUSE_GPU_CLAIMS: u8 = 1 << 4;
+#[constant]
+pub const PAUSE_SELLS: u8 = 1 << 5;
+#[constant]
+pub const PAUSE_TRANSFERS: u8 = 1 << 6;
+#[constant]
+pub const PAUSE_REPAIRS: u8 = 1 << 7;
+pub const PAUSE_ALL: u8 = PAUSE_SOL_BUYS
+    | PAUSE_GPU_BUYS
+    | PAUSE_COMPOUND
+    | PAUSE_SOL_CLAIMS
+    | PAUSE_GPU_CLAIMS
+    | PAUSE_SELLS
+    | PAUSE_TRANSFERS
+    | PAUSE_REPAIRS;
+
+// Selling MH/s: 10% exit fee by default (at most 50%), cooldown of at most 30 days
+pub const DEFAULT_EXIT_FEE_BPS: u16 = 1_000;
+#[constant]
+pub const MAX_EXIT_FEE_BPS: u16 = 5_000;
+#[constant]
+pub const MAX_SELL_COOLDOWN_SECS: u32 = 30 * 24 * 60 * 60;
+
+// transfer_mining_power fee, burned from the amount sent (off by default)
+#[constant]
+pub const MAX_TRANSFER_FEE_BPS: u16 = 2_000;
+
+// Hardware wear: MH/s loses at most 1% a day until repaired (off by default)
+#[constant]
+pub const MAX_DECAY_BPS_PER_DAY: u16 = 100;
+pub const DECAY_INDEX_ONE: u128 = 1_000_000_000_000_000_000;
+
+// Emission schedule: the daily pool steps down by `emission_decay_bps` every epoch
+// of a day to a year (off by default). The upper bound keeps `emission_integral`
+// well inside u128 for any decay.
+#[constant]
+pub const MIN_EMISSION_EPOCH_SECS: u32 = 24 * 60 * 60;
+#[constant]
+pub const MAX_EMISSION_EPOCH_SECS: u32 = 365 * 24 * 60 * 60;
+pub const EMISSION_RATE_PRECISION: u128 = 1_000_000_000;
+
+// Admin proposals wait 48h by default, never less than 24h and never more than
+// 30 days, so a fix can always be queued and run
+pub const DEFAULT_TIMELOCK_DELAY_SECS: u32 = 48 * 60 * 60;
+pub const MIN_TIMELOCK_DELAY_SECS: u32 = 24 * 60 * 60;
+pub const MAX_TIMELOCK_DELAY_SECS: u32 = 30 * 24 * 60 * 60;
+pub const MAX_PROPOSAL_DESCRIPTION_LEN: usize = 256;
+
+// Genesis pricing defaults: flat base_buy_rate until 100k MH/s or $10k TVL,
+// tunable up to 10M MH/s and $1M TVL
+pub const DEFAULT_GENESIS_MHS_THRESHOLD: u64 = 100_000;
+pub const DEFAULT_GENESIS_TVL_THRESHOLD_USD: u64 = 10_000 * 100_000_000;
+#[constant]
+pub const MAX_GENESIS_MHS_THRESHOLD: u64 = 10_000_000;
+#[constant]
+pub const MAX_GENESIS_TVL_THRESHOLD_USD: u64 = 1_000_000 * 100_000_000;
+
 #[program]
 pub mod bakedbeans_solana {
     use super::*;
 
-    /// Initialize with new mining pool model - now with dual currency support
-    pub fn initialize(ctx: Context<Initialize>, seed_amount: u64, dev_wallet: Pubkey) -> Result<()> {
-        let global_state = &mut ctx.accounts.global_state;
-        
+    /// Initialize with new mining pool model - now with dual currency support.
+    /// Seeds the SOL vault with `seed_amount` lamports from the authority and,
+    /// when `gpu_seed_amount > 0`, seeds the GPU vault as well.
+    pub fn initialize(ctx: Context<Initialize>, seed_amount: u64, dev_wallet: Pubkey, gpu_seed_amount: u64) -> Result<()> {
         require!(seed_amount > 0, ErrorCode::InvalidSeedAmount);
+        // The vault PDA holds no data, it only has to stay rent exempt
+        require!(seed_amount >= Rent::get()?.minimum_balance(0), ErrorCode::InvalidSeedAmount);
+        
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.authority.to_account_info(),
+                    to: ctx.accounts.vault.to_account_info(),
+                },
+            ),
+            seed_amount,
+        )?;
+        
+        let mut gpu_token_mint = Pubkey::default(); // Will be set by admin unless seeded here
+        if gpu_seed_amount > 0 {
+            let (Some(gpu_vault), Some(authority_gpu_account), Some(token_program)) = (
+                ctx.accounts.gpu_vault.as_ref(),
+                ctx.accounts.authority_gpu_account.as_ref(),
+                ctx.accounts.token_program.as_ref(),
+            ) else {
+                return err!(ErrorCode::MissingGpuAccounts);
+            };
+            
+            // The GPU vault must be the canonical ATA of the gpu_vault PDA for this mint
+            require!(gpu_vault.owner == ctx.accounts.gpu_vault_authority.key(), ErrorCode::InvalidOwner);
+            require_keys_eq!(
+                gpu_vault.key(),
+                get_associated_token_address(&ctx.accounts.gpu_vault_authority.key(), &gpu_vault.mint),
+                ErrorCode::InvalidGpuVault
+            );
+            require!(authority_gpu_account.mint == gpu_vault.mint, ErrorCode::InvalidMint);
+            require!(authority_gpu_account.owner == ctx.accounts.authority.key(), ErrorCode::InvalidOwner);
+            
+            token::transfer(
+                CpiContext::new(
+                    token_program.to_account_info(),
+                    SplTransfer {
+                        from: authority_gpu_account.to_account_info(),
+                        to: gpu_vault.to_account_info(),
+                        authority: ctx.accounts.authority.to_account_info(),
+                    },
+                ),
+                gpu_seed_amount,
+            )?;
+            
+            gpu_token_mint = gpu_vault.mint;
+        }
+        
+        let global_state = &mut ctx.accounts.global_state;
         
         global_state.authority = ctx.accounts.authority.key();
         global_state.dev_wallet = dev_wallet;
@@ -29,29 +223,104 @@ pub mod bakedbeans_solana {
         global_state.gpu_penalty_bps = 1500; // 15% penalty for GPU buys
         global_state.sol_usd_price = 0; // Will be set by admin
         global_state.gpu_usd_price = 0; // Will be set by admin
-        global_state.gpu_token_mint = Pubkey::default(); // Will be set by admin
+        global_state.gpu_token_mint = gpu_token_mint;
+        global_state.genesis_mhs_threshold = DEFAULT_GENESIS_MHS_THRESHOLD;
+        global_state.genesis_tvl_threshold_usd = DEFAULT_GENESIS_TVL_THRESHOLD_USD;
+        global_state.protocol_seed_sol = seed_amount;
+        global_state.protocol_seed_gpu = gpu_seed_amount;
+        global_state.acc_sol_per_mhs = 0;
+        global_state.acc_gpu_per_mhs = 0;
+        global_state.last_reward_update = Clock::get()?.unix_timestamp;
+        global_state.price_source = PriceSource::Manual;
+        global_state.sol_price_feed = Pubkey::default();
+        global_state.gpu_price_feed = Pubkey::default();
+        global_state.max_price_age_secs = DEFAULT_MAX_PRICE_AGE_SECS;
+        global_state.max_confidence_bps = DEFAULT_MAX_CONFIDENCE_BPS;
+        global_state.price_updated_slot = 0;
+        global_state.price_updated_at = 0;
+        global_state.max_price_move_bps = DEFAULT_MAX_PRICE_MOVE_BPS;
+        global_state.price_window_start = 0;
+        global_state.window_sol_usd_price = 0;
+        global_state.window_gpu_usd_price = 0;
+        global_state.manual_price_max_age_secs = DEFAULT_MANUAL_PRICE_MAX_AGE_SECS;
+        global_state.referral_levels = 1;
+        global_state.referral_bps = [DEFAULT_REFERRAL_BPS, 0, 0, 0, 0];
+        global_state.referral_reward_mode = ReferralRewardMode::MiningPower;
+        global_state.next_proposal_id = 0;
+        global_state.timelock_delay_secs = DEFAULT_TIMELOCK_DELAY_SECS;
+        global_state.pauser = Pubkey::default();
+        global_state.paused = 0;
+        global_state.pending_authority = None;
+        global_state.fee_split_count = 0;
+        global_state.exit_fee_bps = DEFAULT_EXIT_FEE_BPS;
+        global_state.sell_cooldown_secs = 0;
+        global_state.total_locked_mhs = 0;
+        global_state.total_lock_bonus = 0;
+        global_state.next_rig_id = 0;
+        global_state.transfer_fee_bps = 0;
+        global_state.decay_bps_per_day = 0;
+        global_state.decay_index = DECAY_INDEX_ONE;
+        global_state.last_decay_update = global_state.last_reward_update;
+        global_state.total_rig_mhs = 0;
+        global_state.repair_lamports_per_mhs = 0;
+        global_state.repair_gpu_per_mhs = 0;
+        global_state.difficulty = HASH_PER_MHS;
+        global_state.difficulty_epoch_secs = DEFAULT_DIFFICULTY_EPOCH_SECS;
+        global_state.target_growth_bps = DEFAULT_TARGET_GROWTH_BPS;
+        global_state.difficulty_epoch_start = global_state.last_reward_update;
+        global_state.epoch_start_mining_power = 0;
+        global_state.emission_start = 0;
+        global_state.emission_epoch_secs = 0;
+        global_state.emission_decay_bps = 0;
         
-        msg!("Mining Tycoon v2 initialized - Dual Currency Mining Pool Model");
+        emit!(Initialized {
+            authority: global_state.authority,
+            dev_wallet,
+            gpu_token_mint,
+            seed_sol: seed_amount,
+            seed_gpu: gpu_seed_amount,
+            timestamp: global_state.last_reward_update,
+        });
         
         Ok(())
     }
 
     /// Buy MH/s with SOL - NEW: 1% of hashrate = 2% of TVL
     /// SECURITY FIX: SOL transfer happens via CPI to prevent exploit
-    pub fn buy_mining_power(ctx: Context<BuyMiningPower>, amount: u64, referrer: Option<Pubkey>) -> Result<()> {
+    pub fn buy_mining_power<'info>(
+        ctx: Context<'_, '_, 'info, 'info, BuyMiningPower<'info>>,
+        amount: u64,
+        referrer: Option<Pubkey>,
+    ) -> Result<()> {
         let global_state = &mut ctx.accounts.global_state;
         require!(global_state.initialized, ErrorCode::NotInitialized);
+        require_not_paused(global_state, PAUSE_SOL_BUYS)?;
         require!(amount > 0, ErrorCode::InvalidAmount);
+        
+        let gpu_vault_balance = optional_gpu_vault_balance(global_state, ctx.accounts.gpu_vault.as_deref())?;
+        
+        // Pull fresh prices when an on-chain oracle is configured
+        refresh_prices(
+            global_state,
+            ctx.accounts.sol_price_feed.as_deref(),
+            ctx.accounts.gpu_price_feed.as_deref(),
+            PaymentCurrency::Sol,
+            gpu_vault_balance,
+            &Clock::get()?,
+        )?;
         require!(global_state.sol_usd_price > 0, ErrorCode::PriceNotSet);
         
         let user_state = &mut ctx.accounts.user_state;
         let clock = Clock::get()?;
         
-        if user_state.referrer.is_none() && referrer.is_some() {
-            let ref_key = referrer.unwrap();
-            require!(ref_key != ctx.accounts.buyer.key(), ErrorCode::SelfReferral);
-            user_state.referrer = Some(ref_key);
-        }
+        // Release pool rewards at the pre-deposit TVL, then bank the buyer's share
+        update_pools(
+            global_state,
+            ctx.accounts.vault.to_account_info().lamports(),
+            gpu_vault_balance,
+            clock.unix_timestamp,
+        )?;
+        let (settled_hash, settled_sol, settled_gpu) = settle_user(user_state, global_state, clock.unix_timestamp)?;
         
         // SECURITY FIX: Transfer SOL via CPI to ensure payment actually happens
         anchor_lang::system_program::transfer(
@@ -65,185 +334,147 @@ pub mod bakedbeans_solana {
             amount,
         )?;
         
-        // NEW PRICING MODEL: Calculate based on TVL percentage
-        // Convert SOL to USD
-        let sol_usd_value = (amount as u128)
-            .checked_mul(global_state.sol_usd_price as u128)
-            .ok_or(ErrorCode::Overflow)?
-            .checked_div(1_000_000_000) // SOL has 9 decimals, price has 8
-            .ok_or(ErrorCode::DivisionByZero)?;
-        
-        // Calculate total TVL in USD (SOL + GPU)
-        let vault_balance = ctx.accounts.vault.to_account_info().lamports();
-        let sol_tvl_usd = (vault_balance as u128)
-            .checked_mul(global_state.sol_usd_price as u128)
-            .ok_or(ErrorCode::Overflow)?
-            .checked_div(1_000_000_000)
-            .ok_or(ErrorCode::DivisionByZero)?;
-        
-        // Read GPU vault balance from token account data
-        let gpu_vault_balance = if ctx.accounts.gpu_vault.data_len() >= 72 {
-            let data = ctx.accounts.gpu_vault.try_borrow_data()?;
-            u64::from_le_bytes(data[64..72].try_into().unwrap_or([0; 8]))
-        } else {
-            0
-        };
-        
-        let gpu_tvl_usd = (gpu_vault_balance as u128)
-            .checked_mul(global_state.gpu_usd_price as u128)
-            .ok_or(ErrorCode::Overflow)?
-            .checked_div(1_000_000) // GPU has 6 decimals, price has 8
-            .ok_or(ErrorCode::DivisionByZero)?;
-        
-        let total_tvl_usd = sol_tvl_usd.checked_add(gpu_tvl_usd).ok_or(ErrorCode::Overflow)?;
-        
-        // Calculate MH/s using new model
-        let mhs_bought = calculate_mhs_for_usd(
-            sol_usd_value,
-            global_state.total_mining_power,
-            total_tvl_usd
+        // NEW PRICING MODEL: Calculate based on TVL percentage (SOL buys price after the deposit lands)
+        let quote = quote_mhs(
+            global_state,
+            PaymentCurrency::Sol,
+            amount,
+            ctx.accounts.vault.to_account_info().lamports(),
+            gpu_vault_balance,
         )?;
-        
-        // Apply protocol fee
-        let fee_mhs = mhs_bought.checked_mul(global_state.protocol_fee_val as u64)
-            .ok_or(ErrorCode::Overflow)?
-            .checked_div(100)
-            .ok_or(ErrorCode::DivisionByZero)?;
-        let mhs_after_fee = mhs_bought.checked_sub(fee_mhs).ok_or(ErrorCode::Overflow)?;
+        let mhs_after_fee = quote.mhs_after_fee;
         
         // Update user and global state
-        user_state.mining_power = user_state.mining_power
-            .checked_add(mhs_after_fee)
-            .ok_or(ErrorCode::Overflow)?;
-        user_state.last_claim = clock.unix_timestamp;
+        add_user_power(user_state, mhs_after_fee, mhs_after_fee)?;
+        sync_reward_debt(user_state, global_state)?;
         
         global_state.total_mining_power = global_state.total_mining_power
             .checked_add(mhs_after_fee)
             .ok_or(ErrorCode::Overflow)?;
         
-        // Referral bonus (5%) - skip if no referrer or referrer PDA doesn't exist
-        if let Some(ref_account_info) = &ctx.accounts.referrer_state {
-            // Only process if account is initialized (owned by our program)
-            if *ref_account_info.owner == *ctx.program_id && ref_account_info.data_len() >= 8 {
-                // Manually deserialize UserState
-                let data = ref_account_info.try_borrow_data()?;
-                if data.len() >= 40 { // At least has mining_power field
-                    let current_power_bytes: [u8; 8] = data[40..48].try_into().map_err(|_| ErrorCode::Overflow)?;
-                    let current_power = u64::from_le_bytes(current_power_bytes);
-                    
-                    let referral_bonus = mhs_after_fee.checked_div(20).ok_or(ErrorCode::DivisionByZero)?;
-                    let new_power = current_power.checked_add(referral_bonus).ok_or(ErrorCode::Overflow)?;
-                    
-                    // Write back (very unsafe, but needed for optional account)
-                    drop(data);
-                    let mut data = ref_account_info.try_borrow_mut_data()?;
-                    data[40..48].copy_from_slice(&new_power.to_le_bytes());
-                    
-                    global_state.total_mining_power = global_state.total_mining_power
-                        .checked_add(referral_bonus)
-                        .ok_or(ErrorCode::Overflow)?;
-                    msg!("Sent {} MH/s to referrer", referral_bonus);
-                }
-            }
-        }
+        // Remaining accounts: fee split recipients first, then referral levels 2+
+        let (fee_recipients, upline) = split_fee_recipients(global_state, ctx.remaining_accounts, 1)?;
+        
+        // Referral rewards per the referral schedule
+        let (referral_bonus, referral_reward) = settle_referral(
+            global_state,
+            user_state,
+            referrer,
+            ctx.accounts.referrer_state.as_mut(),
+            upline,
+            ReferralPurchase {
+                currency: PaymentCurrency::Sol,
+                amount,
+                mhs_bought: mhs_after_fee,
+            },
+            clock.unix_timestamp,
+        )?;
+        
+        // With a fee split configured, the buy fee (less fee-funded referrals) leaves the vault
+        let buy_fee = routed_buy_fee(global_state, amount, referral_reward)?;
+        let treasury = optional_treasury(global_state, ctx.accounts.treasury.as_ref())?;
+        distribute_sol_fee(
+            global_state,
+            buy_fee,
+            &ctx.accounts.vault.to_account_info(),
+            ctx.bumps.vault,
+            fee_recipients,
+            treasury.as_ref(),
+            &ctx.accounts.system_program.to_account_info(),
+        )?;
         
-        msg!("Bought {} MH/s for {} lamports", mhs_after_fee, amount);
+        emit!(MiningPowerBought {
+            user: ctx.accounts.buyer.key(),
+            currency: PaymentCurrency::Sol,
+            amount,
+            usd_value: quote.usd_value,
+            penalty_usd: quote.penalty_usd,
+            fee_amount: quote.fee_amount,
+            mhs_bought: mhs_after_fee,
+            referrer: user_state.referrer,
+            referral_bonus,
+            referral_reward,
+            mining_power: user_state.mining_power,
+            total_mining_power: global_state.total_mining_power,
+            settled_hash,
+            settled_sol,
+            settled_gpu,
+            timestamp: clock.unix_timestamp,
+        });
         
         Ok(())
     }
 
     /// Buy MH/s with GPU token (15% penalty)
-    pub fn buy_with_gpu(ctx: Context<BuyWithGpu>, amount: u64, referrer: Option<Pubkey>) -> Result<()> {
+    pub fn buy_with_gpu<'info>(
+        ctx: Context<'_, '_, 'info, 'info, BuyWithGpu<'info>>,
+        amount: u64,
+        referrer: Option<Pubkey>,
+    ) -> Result<()> {
         let global_state = &mut ctx.accounts.global_state;
         require!(global_state.initialized, ErrorCode::NotInitialized);
+        require_not_paused(global_state, PAUSE_GPU_BUYS)?;
         require!(amount > 0, ErrorCode::InvalidAmount);
+        
+        // Pull fresh prices when an on-chain oracle is configured
+        refresh_prices(
+            global_state,
+            ctx.accounts.sol_price_feed.as_deref(),
+            ctx.accounts.gpu_price_feed.as_deref(),
+            PaymentCurrency::Gpu,
+            ctx.accounts.gpu_vault.amount,
+            &Clock::get()?,
+        )?;
         require!(global_state.gpu_usd_price > 0, ErrorCode::PriceNotSet);
         require!(global_state.sol_usd_price > 0, ErrorCode::PriceNotSet);
         
         let user_state = &mut ctx.accounts.user_state;
         let clock = Clock::get()?;
         
-        if user_state.referrer.is_none() && referrer.is_some() {
-            let ref_key = referrer.unwrap();
-            require!(ref_key != ctx.accounts.buyer.key(), ErrorCode::SelfReferral);
-            user_state.referrer = Some(ref_key);
-        }
-        
-        // Convert GPU amount to USD equivalent
-        let gpu_usd_value = (amount as u128)
-            .checked_mul(global_state.gpu_usd_price as u128)
-            .ok_or(ErrorCode::Overflow)?
-            .checked_div(10u128.pow(GPU_TOKEN_DECIMALS as u32))
-            .ok_or(ErrorCode::DivisionByZero)?;
-        
-        // Convert to SOL equivalent based on USD value
-        let sol_equivalent = gpu_usd_value
-            .checked_mul(10u128.pow(9)) // SOL has 9 decimals
-            .ok_or(ErrorCode::Overflow)?
-            .checked_div(global_state.sol_usd_price as u128)
-            .ok_or(ErrorCode::DivisionByZero)?;
-        
-        let sol_amount = u64::try_from(sol_equivalent).map_err(|_| ErrorCode::Overflow)?;
-        
-        // Apply 15% penalty (GPU buyers pay more)
-        let gpu_usd_with_penalty = gpu_usd_value
-            .checked_mul(10000 + global_state.gpu_penalty_bps as u128)
-            .ok_or(ErrorCode::Overflow)?
-            .checked_div(10000)
-            .ok_or(ErrorCode::DivisionByZero)?;
-        
-        // Calculate total TVL in USD (SOL + GPU)
-        let sol_vault_balance = ctx.accounts.sol_vault.to_account_info().lamports();
-        let sol_tvl_usd = (sol_vault_balance as u128)
-            .checked_mul(global_state.sol_usd_price as u128)
-            .ok_or(ErrorCode::Overflow)?
-            .checked_div(1_000_000_000)
-            .ok_or(ErrorCode::DivisionByZero)?;
-        
-        let gpu_vault_balance = ctx.accounts.gpu_vault.amount;
-        let gpu_tvl_usd = (gpu_vault_balance as u128)
-            .checked_mul(global_state.gpu_usd_price as u128)
-            .ok_or(ErrorCode::Overflow)?
-            .checked_div(1_000_000)
-            .ok_or(ErrorCode::DivisionByZero)?;
-        
-        let total_tvl_usd = sol_tvl_usd.checked_add(gpu_tvl_usd).ok_or(ErrorCode::Overflow)?;
-        
-        // Calculate MH/s using new pricing model (after penalty)
-        let mhs_bought = calculate_mhs_for_usd(
-            gpu_usd_with_penalty,
-            global_state.total_mining_power,
-            total_tvl_usd
+        // Release pool rewards at the pre-deposit TVL, then bank the buyer's share
+        update_pools(
+            global_state,
+            ctx.accounts.sol_vault.to_account_info().lamports(),
+            ctx.accounts.gpu_vault.amount,
+            clock.unix_timestamp,
         )?;
+        let (settled_hash, settled_sol, settled_gpu) = settle_user(user_state, global_state, clock.unix_timestamp)?;
         
-        // Apply protocol fee
-        let fee_mhs = mhs_bought.checked_mul(global_state.protocol_fee_val as u64)
-            .ok_or(ErrorCode::Overflow)?
-            .checked_div(100)
-            .ok_or(ErrorCode::DivisionByZero)?;
-        let mhs_after_fee = mhs_bought.checked_sub(fee_mhs).ok_or(ErrorCode::Overflow)?;
+        // Calculate MH/s using new pricing model (with the GPU penalty applied)
+        let quote = quote_mhs(
+            global_state,
+            PaymentCurrency::Gpu,
+            amount,
+            ctx.accounts.sol_vault.to_account_info().lamports(),
+            ctx.accounts.gpu_vault.amount,
+        )?;
+        let mhs_after_fee = quote.mhs_after_fee;
         
         // Update user and global state
-        user_state.mining_power = user_state.mining_power
-            .checked_add(mhs_after_fee)
-            .ok_or(ErrorCode::Overflow)?;
-        user_state.last_claim = clock.unix_timestamp;
+        add_user_power(user_state, mhs_after_fee, mhs_after_fee)?;
+        sync_reward_debt(user_state, global_state)?;
         
         global_state.total_mining_power = global_state.total_mining_power
             .checked_add(mhs_after_fee)
             .ok_or(ErrorCode::Overflow)?;
         
-        // Referral bonus (5%)
-        if let Some(referrer_state) = ctx.accounts.referrer_state.as_mut() {
-            let referral_bonus = mhs_after_fee.checked_div(20).ok_or(ErrorCode::DivisionByZero)?;
-            referrer_state.mining_power = referrer_state.mining_power
-                .checked_add(referral_bonus)
-                .ok_or(ErrorCode::Overflow)?;
-            global_state.total_mining_power = global_state.total_mining_power
-                .checked_add(referral_bonus)
-                .ok_or(ErrorCode::Overflow)?;
-            msg!("Sent {} MH/s to referrer", referral_bonus);
-        }
+        // Remaining accounts: fee split recipients first, then referral levels 2+
+        let (fee_recipients, upline) = split_fee_recipients(global_state, ctx.remaining_accounts, 1)?;
+        
+        // Referral rewards per the referral schedule
+        let (referral_bonus, referral_reward) = settle_referral(
+            global_state,
+            user_state,
+            referrer,
+            ctx.accounts.referrer_state.as_mut(),
+            upline,
+            ReferralPurchase {
+                currency: PaymentCurrency::Gpu,
+                amount,
+                mhs_bought: mhs_after_fee,
+            },
+            clock.unix_timestamp,
+        )?;
         
         // Transfer GPU tokens to vault
         token::transfer(
@@ -258,7 +489,37 @@ pub mod bakedbeans_solana {
             amount,
         )?;
         
-        msg!("Bought {} MH/s with {} GPU tokens (penalty applied)", mhs_after_fee, amount);
+        // With a fee split configured, the buy fee (less fee-funded referrals) leaves the vault
+        let buy_fee = routed_buy_fee(global_state, amount, referral_reward)?;
+        distribute_gpu_fee(
+            global_state,
+            buy_fee,
+            &ctx.accounts.gpu_vault.to_account_info(),
+            &ctx.accounts.gpu_vault_authority,
+            ctx.bumps.gpu_vault_authority,
+            fee_recipients,
+            &ctx.accounts.treasury_gpu_account.to_account_info(),
+            &ctx.accounts.token_program.to_account_info(),
+        )?;
+        
+        emit!(MiningPowerBought {
+            user: ctx.accounts.buyer.key(),
+            currency: PaymentCurrency::Gpu,
+            amount,
+            usd_value: quote.usd_value,
+            penalty_usd: quote.penalty_usd,
+            fee_amount: quote.fee_amount,
+            mhs_bought: mhs_after_fee,
+            referrer: user_state.referrer,
+            referral_bonus,
+            referral_reward,
+            mining_power: user_state.mining_power,
+            total_mining_power: global_state.total_mining_power,
+            settled_hash,
+            settled_sol,
+            settled_gpu,
+            timestamp: clock.unix_timestamp,
+        });
         
         Ok(())
     }
@@ -267,114 +528,98 @@ pub mod bakedbeans_solana {
     pub fn compound_hash(ctx: Context<CompoundHash>) -> Result<()> {
         let global_state = &mut ctx.accounts.global_state;
         require!(global_state.initialized, ErrorCode::NotInitialized);
+        require_not_paused(global_state, PAUSE_COMPOUND)?;
         
         let user_state = &mut ctx.accounts.user_state;
         let clock = Clock::get()?;
         
-        require!(user_state.mining_power > 0, ErrorCode::InvalidAmount);
-        
-        // Calculate hash generated since last claim
-        let time_passed = (clock.unix_timestamp - user_state.last_claim) as u64;
-        let hash_generated = time_passed.checked_mul(user_state.mining_power).ok_or(ErrorCode::Overflow)?;
-        
-        // Add stored unclaimed hash
-        let total_hash = user_state.unclaimed_earnings.checked_add(hash_generated).ok_or(ErrorCode::Overflow)?;
+        // Release pool rewards, then bank this user's hash and rewards at the old power
+        let gpu_vault_balance = optional_gpu_vault_balance(global_state, ctx.accounts.gpu_vault.as_deref())?;
+        update_pools(
+            global_state,
+            ctx.accounts.sol_vault.to_account_info().lamports(),
+            gpu_vault_balance,
+            clock.unix_timestamp,
+        )?;
+        settle_user(user_state, global_state, clock.unix_timestamp)?;
+        let total_hash = user_state.unclaimed_hash;
         require!(total_hash > 0, ErrorCode::InvalidAmount);
         
-        // Convert hash to MH/s (no fee!)
-        // Use simple rate: 86,400 hash = 1 MH/s (1 day)
-        let new_mhs = total_hash / 86_400;
+        // Convert hash to MH/s (no fee!) at the current difficulty
+        retarget_difficulty(global_state, clock.unix_timestamp)?;
+        let difficulty = difficulty(global_state);
+        let new_mhs = total_hash / difficulty;
         require!(new_mhs > 0, ErrorCode::InvalidAmount);
+        let hash_spent = new_mhs.checked_mul(difficulty).ok_or(ErrorCode::Overflow)?;
         
-        // Update state
-        user_state.mining_power = user_state.mining_power.checked_add(new_mhs).ok_or(ErrorCode::Overflow)?;
-        user_state.unclaimed_earnings = 0;
-        user_state.last_claim = clock.unix_timestamp;
+        // Update state - leftover hash stays banked, SOL/GPU ledgers are untouched
+        add_user_power(user_state, new_mhs, new_mhs)?;
+        user_state.unclaimed_hash = total_hash.checked_sub(hash_spent).ok_or(ErrorCode::Overflow)?;
+        sync_reward_debt(user_state, global_state)?;
         
         global_state.total_mining_power = global_state.total_mining_power.checked_add(new_mhs).ok_or(ErrorCode::Overflow)?;
         
-        msg!("Compounded {} hash into {} MH/s (no fee!)", total_hash, new_mhs);
+        emit!(HashCompounded {
+            user: ctx.accounts.user.key(),
+            hash_spent,
+            mhs_added: new_mhs,
+            unclaimed_hash: user_state.unclaimed_hash,
+            difficulty,
+            mining_power: user_state.mining_power,
+            total_mining_power: global_state.total_mining_power,
+            timestamp: clock.unix_timestamp,
+        });
         
         Ok(())
     }
 
     /// Claim accumulated SOL and GPU earnings (dual currency)
-    pub fn claim_earnings(ctx: Context<ClaimEarnings>) -> Result<()> {
+    pub fn claim_earnings<'info>(ctx: Context<'_, '_, 'info, 'info, ClaimEarnings<'info>>) -> Result<()> {
         let global_state = &mut ctx.accounts.global_state;
         require!(global_state.initialized, ErrorCode::NotInitialized);
         
         let user_state = &mut ctx.accounts.user_state;
         let clock = Clock::get()?;
         
-        require!(user_state.mining_power > 0, ErrorCode::InvalidAmount);
-        
-        // Calculate new SOL earnings
+        // Release pool rewards and bank this user's share into the SOL/GPU ledgers
         let sol_vault_balance = ctx.accounts.sol_vault.to_account_info().lamports();
-        let mineable_sol_tvl = sol_vault_balance.checked_sub(global_state.total_unclaimed_sol).ok_or(ErrorCode::Overflow)?;
+        let gpu_vault_balance = optional_gpu_vault_balance(global_state, ctx.accounts.gpu_vault.as_deref())?;
+        update_pools(global_state, sol_vault_balance, gpu_vault_balance, clock.unix_timestamp)?;
+        settle_user(user_state, global_state, clock.unix_timestamp)?;
         
-        let new_sol_earnings = calculate_earnings(
-            user_state.mining_power,
-            global_state.total_mining_power,
-            user_state.last_claim,
-            clock.unix_timestamp,
-            mineable_sol_tvl,
-            global_state.daily_pool_percentage
-        )?;
-        
-        // Calculate new GPU earnings
-        let gpu_vault_balance = ctx.accounts.gpu_vault.amount;
-        let mineable_gpu_tvl = gpu_vault_balance.checked_sub(global_state.total_unclaimed_gpu).ok_or(ErrorCode::Overflow)?;
-        
-        let new_gpu_earnings = calculate_earnings(
-            user_state.mining_power,
-            global_state.total_mining_power,
-            user_state.last_claim,
-            clock.unix_timestamp,
-            mineable_gpu_tvl,
-            global_state.daily_pool_percentage
-        )?;
+        user_state.last_claim = clock.unix_timestamp;
         
-        // Add to unclaimed
-        user_state.unclaimed_earnings = user_state.unclaimed_earnings
-            .checked_add(new_sol_earnings)
-            .ok_or(ErrorCode::Overflow)?;
-        user_state.unclaimed_gpu_earnings = user_state.unclaimed_gpu_earnings
-            .checked_add(new_gpu_earnings)
-            .ok_or(ErrorCode::Overflow)?;
+        // Claim all unclaimed SOL and GPU (the hash ledger is left alone); a paused
+        // currency stays banked until it is unpaused
+        let sol_claims_open = global_state.paused & PAUSE_SOL_CLAIMS == 0;
+        let gpu_claims_open = global_state.paused & PAUSE_GPU_CLAIMS == 0;
+        require!(sol_claims_open || gpu_claims_open, ErrorCode::Paused);
         
-        global_state.total_unclaimed_sol = global_state.total_unclaimed_sol
-            .checked_add(new_sol_earnings)
-            .ok_or(ErrorCode::Overflow)?;
-        global_state.total_unclaimed_gpu = global_state.total_unclaimed_gpu
-            .checked_add(new_gpu_earnings)
-            .ok_or(ErrorCode::Overflow)?;
+        let total_sol_to_claim = if sol_claims_open { user_state.unclaimed_sol } else { 0 };
+        let total_gpu_to_claim = if gpu_claims_open { user_state.unclaimed_gpu } else { 0 };
         
-        user_state.last_claim = clock.unix_timestamp;
+        require!(total_sol_to_claim > 0 || total_gpu_to_claim > 0, ErrorCode::InvalidAmount);
         
-        // Claim all unclaimed SOL
-        let total_sol_to_claim = user_state.unclaimed_earnings;
-        let total_gpu_to_claim = user_state.unclaimed_gpu_earnings;
+        let (sol_fee, sol_payout) = split_protocol_fee(total_sol_to_claim, global_state.protocol_fee_val)?;
+        let (gpu_fee, gpu_payout) = split_protocol_fee(total_gpu_to_claim, global_state.protocol_fee_val)?;
         
-        require!(total_sol_to_claim > 0 || total_gpu_to_claim > 0, ErrorCode::InvalidAmount);
+        // With a fee split configured, remaining accounts are the recipients' wallets
+        // followed by their GPU token accounts; otherwise fees go to the treasury
+        let (fee_recipients, _) = split_fee_recipients(global_state, ctx.remaining_accounts, 2)?;
+        let (sol_fee_recipients, gpu_fee_recipients) = fee_recipients.split_at(global_state.fee_split_count as usize);
         
         // Process SOL claim
         if total_sol_to_claim > 0 {
             require!(sol_vault_balance >= total_sol_to_claim, ErrorCode::InsufficientFunds);
             
-            let sol_fee = total_sol_to_claim.checked_mul(global_state.protocol_fee_val as u64)
-                .ok_or(ErrorCode::Overflow)?
-                .checked_div(100)
-                .ok_or(ErrorCode::DivisionByZero)?;
-            let sol_payout = total_sol_to_claim.checked_sub(sol_fee).ok_or(ErrorCode::Overflow)?;
-            
-            user_state.unclaimed_earnings = 0;
+            user_state.unclaimed_sol = 0;
             user_state.total_sol_claimed = user_state.total_sol_claimed
                 .checked_add(sol_payout)
                 .ok_or(ErrorCode::Overflow)?;
             
-            global_state.total_unclaimed_sol = global_state.total_unclaimed_sol
-                .checked_sub(total_sol_to_claim)
-                .ok_or(ErrorCode::Overflow)?;
+            // Holders round down separately from the pool, so the last claimer can
+            // be owed a lamport more than is left reserved
+            global_state.total_unclaimed_sol = global_state.total_unclaimed_sol.saturating_sub(total_sol_to_claim);
             
             let vault_bump = ctx.bumps.sol_vault;
             let signer_seeds: &[&[&[u8]]] = &[&[b"vault", &[vault_bump]]];
@@ -391,39 +636,55 @@ pub mod bakedbeans_solana {
                 sol_payout,
             )?;
             
-            anchor_lang::system_program::transfer(
-                CpiContext::new_with_signer(
-                    ctx.accounts.system_program.to_account_info(),
-                    anchor_lang::system_program::Transfer {
-                        from: ctx.accounts.sol_vault.to_account_info(),
-                        to: ctx.accounts.dev_wallet.to_account_info(),
-                    },
-                    signer_seeds,
-                ),
-                sol_fee,
-            )?;
-            
-            msg!("Claimed {} SOL (fee: {})", sol_payout, sol_fee);
+            // Before the treasury exists an unsplit fee stays in the vault
+            let treasury = optional_treasury(global_state, ctx.accounts.treasury.as_ref())?;
+            if global_state.fee_split_count == 0 {
+                if let Some(treasury) = treasury {
+                    anchor_lang::system_program::transfer(
+                        CpiContext::new_with_signer(
+                            ctx.accounts.system_program.to_account_info(),
+                            anchor_lang::system_program::Transfer {
+                                from: ctx.accounts.sol_vault.to_account_info(),
+                                to: treasury,
+                            },
+                            signer_seeds,
+                        ),
+                        sol_fee,
+                    )?;
+                    global_state.total_sol_fees = global_state.total_sol_fees
+                        .checked_add(sol_fee)
+                        .ok_or(ErrorCode::Overflow)?;
+                }
+            } else {
+                distribute_sol_fee(
+                    global_state,
+                    sol_fee,
+                    &ctx.accounts.sol_vault.to_account_info(),
+                    vault_bump,
+                    sol_fee_recipients,
+                    treasury.as_ref(),
+                    &ctx.accounts.system_program.to_account_info(),
+                )?;
+            }
         }
         
         // Process GPU claim
         if total_gpu_to_claim > 0 {
+            let (Some(gpu_vault), Some(user_gpu_account), Some(treasury_gpu_account)) = (
+                ctx.accounts.gpu_vault.as_ref(),
+                ctx.accounts.user_gpu_account.as_ref(),
+                ctx.accounts.treasury_gpu_account.as_ref(),
+            ) else {
+                return err!(ErrorCode::MissingGpuAccounts);
+            };
             require!(gpu_vault_balance >= total_gpu_to_claim, ErrorCode::InsufficientFunds);
             
-            let gpu_fee = total_gpu_to_claim.checked_mul(global_state.protocol_fee_val as u64)
-                .ok_or(ErrorCode::Overflow)?
-                .checked_div(100)
-                .ok_or(ErrorCode::DivisionByZero)?;
-            let gpu_payout = total_gpu_to_claim.checked_sub(gpu_fee).ok_or(ErrorCode::Overflow)?;
-            
-            user_state.unclaimed_gpu_earnings = 0;
+            user_state.unclaimed_gpu = 0;
             user_state.total_gpu_claimed = user_state.total_gpu_claimed
                 .checked_add(gpu_payout)
                 .ok_or(ErrorCode::Overflow)?;
             
-            global_state.total_unclaimed_gpu = global_state.total_unclaimed_gpu
-                .checked_sub(total_gpu_to_claim)
-                .ok_or(ErrorCode::Overflow)?;
+            global_state.total_unclaimed_gpu = global_state.total_unclaimed_gpu.saturating_sub(total_gpu_to_claim);
             
             let gpu_vault_bump = ctx.bumps.gpu_vault_authority;
             let gpu_signer_seeds: &[&[&[u8]]] = &[&[b"gpu_vault", &[gpu_vault_bump]]];
@@ -432,8 +693,8 @@ pub mod bakedbeans_solana {
                 CpiContext::new_with_signer(
                     ctx.accounts.token_program.to_account_info(),
                     SplTransfer {
-                        from: ctx.accounts.gpu_vault.to_account_info(),
-                        to: ctx.accounts.user_gpu_account.to_account_info(),
+                        from: gpu_vault.to_account_info(),
+                        to: user_gpu_account.to_account_info(),
                         authority: ctx.accounts.gpu_vault_authority.to_account_info(),
                     },
                     gpu_signer_seeds,
@@ -441,390 +702,4859 @@ pub mod bakedbeans_solana {
                 gpu_payout,
             )?;
             
-            token::transfer(
-                CpiContext::new_with_signer(
-                    ctx.accounts.token_program.to_account_info(),
-                    SplTransfer {
-                        from: ctx.accounts.gpu_vault.to_account_info(),
-                        to: ctx.accounts.dev_gpu_account.to_account_info(),
-                        authority: ctx.accounts.gpu_vault_authority.to_account_info(),
-                    },
-                    gpu_signer_seeds,
-                ),
-                gpu_fee,
-            )?;
-            
-            msg!("Claimed {} GPU tokens (fee: {})", gpu_payout, gpu_fee);
+            if global_state.fee_split_count == 0 {
+                token::transfer(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.token_program.to_account_info(),
+                        SplTransfer {
+                            from: gpu_vault.to_account_info(),
+                            to: treasury_gpu_account.to_account_info(),
+                            authority: ctx.accounts.gpu_vault_authority.to_account_info(),
+                        },
+                        gpu_signer_seeds,
+                    ),
+                    gpu_fee,
+                )?;
+                global_state.total_gpu_fees = global_state.total_gpu_fees
+                    .checked_add(gpu_fee)
+                    .ok_or(ErrorCode::Overflow)?;
+            } else {
+                distribute_gpu_fee(
+                    global_state,
+                    gpu_fee,
+                    &gpu_vault.to_account_info(),
+                    &ctx.accounts.gpu_vault_authority,
+                    gpu_vault_bump,
+                    gpu_fee_recipients,
+                    &treasury_gpu_account.to_account_info(),
+                    &ctx.accounts.token_program.to_account_info(),
+                )?;
+            }
         }
         
+        emit!(EarningsClaimed {
+            user: ctx.accounts.user.key(),
+            sol_payout,
+            sol_fee,
+            gpu_payout,
+            gpu_fee,
+            total_sol_claimed: user_state.total_sol_claimed,
+            total_gpu_claimed: user_state.total_gpu_claimed,
+            mining_power: user_state.mining_power,
+            timestamp: clock.unix_timestamp,
+        });
+        
         Ok(())
     }
 
-    /// Initialize user account
-    pub fn init_user(ctx: Context<InitUser>) -> Result<()> {
-        let user_state = &mut ctx.accounts.user_state;
+    /// Send `amount` MH/s to `recipient`, creating their user account if needed.
+    /// Both sides are settled first; the transfer fee is burned from the amount sent.
+    pub fn transfer_mining_power(ctx: Context<TransferMiningPower>, amount: u64, recipient: Pubkey) -> Result<()> {
+        let global_state = &mut ctx.accounts.global_state;
+        require!(global_state.initialized, ErrorCode::NotInitialized);
+        require_not_paused(global_state, PAUSE_TRANSFERS)?;
+        
+        let sender_state = &mut ctx.accounts.sender_state;
+        let recipient_state = &mut ctx.accounts.recipient_state;
         let clock = Clock::get()?;
         
-        user_state.owner = ctx.accounts.user.key();
-        user_state.mining_power = 0;
-        user_state.unclaimed_earnings = 0;
-        user_state.unclaimed_gpu_earnings = 0;
-        user_state.last_claim = clock.unix_timestamp;
-        user_state.referrer = None;
-        user_state.total_sol_claimed = 0;
-        user_state.total_gpu_claimed = 0;
+        require!(amount > 0 && amount <= sender_state.mining_power, ErrorCode::InvalidAmount);
         
-        msg!("User initialized");
+        let gpu_vault_balance = optional_gpu_vault_balance(global_state, ctx.accounts.gpu_vault.as_deref())?;
+        update_pools(
+            global_state,
+            ctx.accounts.sol_vault.to_account_info().lamports(),
+            gpu_vault_balance,
+            clock.unix_timestamp,
+        )?;
         
-        Ok(())
-    }
-
-    /// Admin: Drain vault
-    pub fn drain_vault(ctx: Context<DrainVault>, amount: u64) -> Result<()> {
-        let vault_bump = ctx.bumps.vault;
-        let signer_seeds: &[&[&[u8]]] = &[&[b"vault", &[vault_bump]]];
+        if recipient_state.owner == Pubkey::default() {
+            reset_user_state(recipient_state, recipient, clock.unix_timestamp);
+            
+            emit!(UserInitialized {
+                user: recipient,
+                timestamp: clock.unix_timestamp,
+            });
+        }
         
-        anchor_lang::system_program::transfer(
-            CpiContext::new_with_signer(
-                ctx.accounts.system_program.to_account_info(),
-                anchor_lang::system_program::Transfer {
-                    from: ctx.accounts.vault.to_account_info(),
-                    to: ctx.accounts.authority.to_account_info(),
-                },
-                signer_seeds,
-            ),
-            amount,
-        )?;
+        settle_user(sender_state, global_state, clock.unix_timestamp)?;
+        settle_user(recipient_state, global_state, clock.unix_timestamp)?;
         
-        msg!("Drained {} lamports from vault", amount);
+        let (fee, received) = transfer_user_power(sender_state, recipient_state, global_state, amount)?;
+        
+        sync_reward_debt(sender_state, global_state)?;
+        sync_reward_debt(recipient_state, global_state)?;
+        
+        emit!(MiningPowerTransferred {
+            from: sender_state.owner,
+            to: recipient,
+            amount,
+            fee,
+            received,
+            sender_mining_power: sender_state.mining_power,
+            recipient_mining_power: recipient_state.mining_power,
+            total_mining_power: global_state.total_mining_power,
+            timestamp: clock.unix_timestamp,
+        });
         
         Ok(())
     }
 
-    /// Admin: Reset user mining power
-    pub fn reset_user_power(ctx: Context<ResetUser>) -> Result<()> {
+    /// Sell `mhs_amount` MH/s for the seller's share of the net SOL and GPU TVL
+    /// (vault balances less everything already owed to users and the protocol seed),
+    /// minus the exit fee, which stays in the vaults for the remaining miners. With a sell cooldown the proceeds are
+    /// reserved now and paid by `claim_sell_proceeds` once it passes.
+    pub fn sell_mining_power(ctx: Context<SellMiningPower>, mhs_amount: u64) -> Result<()> {
+        let global_state = &mut ctx.accounts.global_state;
+        require!(global_state.initialized, ErrorCode::NotInitialized);
+        require_not_paused(global_state, PAUSE_SELLS)?;
+        
         let user_state = &mut ctx.accounts.user_state;
-        let old_power = user_state.mining_power;
+        let clock = Clock::get()?;
+        
+        require!(mhs_amount > 0 && mhs_amount <= user_state.mining_power, ErrorCode::InvalidAmount);
         
-        // Subtract from global total
-        ctx.accounts.global_state.total_mining_power = ctx.accounts.global_state.total_mining_power
-            .checked_sub(old_power)
+        // Release pool rewards and bank the seller's share before the power moves
+        let sol_vault_balance = ctx.accounts.sol_vault.to_account_info().lamports();
+        let gpu_vault_balance = optional_gpu_vault_balance(global_state, ctx.accounts.gpu_vault.as_deref())?;
+        update_pools(global_state, sol_vault_balance, gpu_vault_balance, clock.unix_timestamp)?;
+        settle_user(user_state, global_state, clock.unix_timestamp)?;
+        
+        let quote = quote_sell(
+            global_state,
+            mhs_amount,
+            sol_vault_balance,
+            gpu_vault_balance,
+            Rent::get()?.minimum_balance(0),
+        )?;
+        let (sol_payout, sol_fee) = (quote.sol_payout, quote.sol_fee);
+        let (gpu_payout, gpu_fee) = (quote.gpu_payout, quote.gpu_fee);
+        
+        remove_user_power(user_state, mhs_amount)?;
+        sync_reward_debt(user_state, global_state)?;
+        
+        global_state.total_mining_power = global_state.total_mining_power
+            .checked_sub(mhs_amount)
             .ok_or(ErrorCode::Overflow)?;
         
-        user_state.mining_power = 0;
-        user_state.unclaimed_earnings = 0;
-        user_state.unclaimed_gpu_earnings = 0;
+        let unlock_at = if global_state.sell_cooldown_secs == 0 {
+            0
+        } else {
+            // Reserve the proceeds like unclaimed earnings until the cooldown passes
+            global_state.total_unclaimed_sol = global_state.total_unclaimed_sol
+                .checked_add(sol_payout)
+                .ok_or(ErrorCode::Overflow)?;
+            global_state.total_unclaimed_gpu = global_state.total_unclaimed_gpu
+                .checked_add(gpu_payout)
+                .ok_or(ErrorCode::Overflow)?;
+            user_state.pending_sell_sol = user_state.pending_sell_sol
+                .checked_add(sol_payout)
+                .ok_or(ErrorCode::Overflow)?;
+            user_state.pending_sell_gpu = user_state.pending_sell_gpu
+                .checked_add(gpu_payout)
+                .ok_or(ErrorCode::Overflow)?;
+            
+            // A new sell restarts the cooldown for everything queued
+            user_state.sell_unlock_at = clock.unix_timestamp
+                .checked_add(global_state.sell_cooldown_secs as i64)
+                .ok_or(ErrorCode::Overflow)?;
+            user_state.sell_unlock_at
+        };
+        
+        emit!(MiningPowerSold {
+            user: ctx.accounts.user.key(),
+            mhs_sold: mhs_amount,
+            sol_payout,
+            sol_fee,
+            gpu_payout,
+            gpu_fee,
+            unlock_at,
+            mining_power: user_state.mining_power,
+            total_mining_power: global_state.total_mining_power,
+            timestamp: clock.unix_timestamp,
+        });
         
-        msg!("Reset user mining power from {} to 0", old_power);
+        if unlock_at == 0 {
+            ctx.accounts.pay_from_vaults(ctx.bumps.sol_vault, ctx.bumps.gpu_vault_authority, sol_payout, gpu_payout)?;
+        }
         
         Ok(())
     }
 
-    /// Admin: Update price oracle (SOL and GPU prices in USD with 8 decimals)
-    pub fn update_prices(ctx: Context<UpdatePrices>, sol_usd_price: u64, gpu_usd_price: u64) -> Result<()> {
+    /// Pay out sell proceeds queued by `sell_mining_power` once the cooldown has passed
+    pub fn claim_sell_proceeds(ctx: Context<SellMiningPower>) -> Result<()> {
         let global_state = &mut ctx.accounts.global_state;
+        require_not_paused(global_state, PAUSE_SELLS)?;
         
-        require!(sol_usd_price > 0, ErrorCode::InvalidAmount);
-        require!(gpu_usd_price > 0, ErrorCode::InvalidAmount);
+        let user_state = &mut ctx.accounts.user_state;
+        let clock = Clock::get()?;
         
-        global_state.sol_usd_price = sol_usd_price;
-        global_state.gpu_usd_price = gpu_usd_price;
+        let sol_payout = user_state.pending_sell_sol;
+        let gpu_payout = user_state.pending_sell_gpu;
+        require!(sol_payout > 0 || gpu_payout > 0, ErrorCode::InvalidAmount);
+        require!(clock.unix_timestamp >= user_state.sell_unlock_at, ErrorCode::SellCooldownActive);
         
-        msg!("Updated prices - SOL: ${}, GPU: ${}", 
-            sol_usd_price as f64 / 100_000_000.0,
-            gpu_usd_price as f64 / 100_000_000.0
-        );
+        user_state.pending_sell_sol = 0;
+        user_state.pending_sell_gpu = 0;
+        global_state.total_unclaimed_sol = global_state.total_unclaimed_sol.saturating_sub(sol_payout);
+        global_state.total_unclaimed_gpu = global_state.total_unclaimed_gpu.saturating_sub(gpu_payout);
+        
+        ctx.accounts.pay_from_vaults(ctx.bumps.sol_vault, ctx.bumps.gpu_vault_authority, sol_payout, gpu_payout)?;
+        
+        emit!(SellProceedsClaimed {
+            user: ctx.accounts.user.key(),
+            sol_payout,
+            gpu_payout,
+            timestamp: clock.unix_timestamp,
+        });
         
         Ok(())
     }
 
-    /// Admin: Set GPU token mint address (for testing different tokens)
-    pub fn set_gpu_token(ctx: Context<SetGpuToken>, gpu_token_mint: Pubkey) -> Result<()> {
+    /// Lock `mhs_amount` MH/s for a fixed term in a new position PDA under the user.
+    /// Locked MH/s earns pool rewards at the term's multiplier and keeps generating
+    /// hash; it cannot be unlocked before the term ends.
+    pub fn lock_mining_power(ctx: Context<LockMiningPower>, mhs_amount: u64, term: LockTerm) -> Result<()> {
         let global_state = &mut ctx.accounts.global_state;
+        require!(global_state.initialized, ErrorCode::NotInitialized);
+        require_not_paused(global_state, PAUSE_COMPOUND)?;
         
-        require!(gpu_token_mint != Pubkey::default(), ErrorCode::InvalidAmount);
+        let user_state = &mut ctx.accounts.user_state;
+        let position = &mut ctx.accounts.position;
+        let clock = Clock::get()?;
         
-        global_state.gpu_token_mint = gpu_token_mint;
+        require!(mhs_amount > 0 && mhs_amount <= user_state.mining_power, ErrorCode::InvalidAmount);
+        
+        let gpu_vault_balance = optional_gpu_vault_balance(global_state, ctx.accounts.gpu_vault.as_deref())?;
+        update_pools(
+            global_state,
+            ctx.accounts.sol_vault.to_account_info().lamports(),
+            gpu_vault_balance,
+            clock.unix_timestamp,
+        )?;
+        settle_user(user_state, global_state, clock.unix_timestamp)?;
+        
+        let rated = remove_user_power(user_state, mhs_amount)?;
+        user_state.locked_mining_power = user_state.locked_mining_power
+            .checked_add(rated)
+            .ok_or(ErrorCode::Overflow)?;
+        sync_reward_debt(user_state, global_state)?;
+        
+        let multiplier_bps = term.multiplier_bps();
+        let reward_weight = term.reward_weight(mhs_amount)?;
+        
+        position.owner = ctx.accounts.user.key();
+        position.id = user_state.next_lock_id;
+        position.mining_power = mhs_amount;
+        position.multiplier_bps = multiplier_bps;
+        position.reward_weight = reward_weight;
+        position.locked_at = clock.unix_timestamp;
+        position.unlock_at = clock.unix_timestamp
+            .checked_add(term.duration_secs())
+            .ok_or(ErrorCode::Overflow)?;
+        position.last_hash_update = clock.unix_timestamp;
+        position.bump = ctx.bumps.position;
+        position.rated_mining_power = rated;
+        position.decay_snapshot = global_state.decay_index;
+        sync_position_debt(position, global_state)?;
+        
+        user_state.next_lock_id = user_state.next_lock_id
+            .checked_add(1)
+            .ok_or(ErrorCode::Overflow)?;
+        
+        // Locked MH/s stays in total_mining_power; only the multiplier's extra weight is new
+        global_state.total_locked_mhs = global_state.total_locked_mhs
+            .checked_add(mhs_amount)
+            .ok_or(ErrorCode::Overflow)?;
+        global_state.total_lock_bonus = global_state.total_lock_bonus
+            .checked_add(reward_weight - mhs_amount)
+            .ok_or(ErrorCode::Overflow)?;
         
-        msg!("GPU token mint updated to: {}", gpu_token_mint);
+        emit!(MiningPowerLocked {
+            user: position.owner,
+            position_id: position.id,
+            mhs_locked: mhs_amount,
+            multiplier_bps,
+            reward_weight,
+            unlock_at: position.unlock_at,
+            timestamp: clock.unix_timestamp,
+        });
         
         Ok(())
     }
+
+    /// Move a locked position's hash and SOL/GPU rewards into the owner's ledgers,
+    /// from where `compound_hash` and `claim_earnings` pick them up
+    pub fn harvest_position(ctx: Context<HarvestPosition>) -> Result<()> {
+        let global_state = &mut ctx.accounts.global_state;
+        require_not_paused(global_state, PAUSE_COMPOUND)?;
+        let user_state = &mut ctx.accounts.user_state;
+        let position = &mut ctx.accounts.position;
+        let clock = Clock::get()?;
+        
+        let gpu_vault_balance = optional_gpu_vault_balance(global_state, ctx.accounts.gpu_vault.as_deref())?;
+        update_pools(
+            global_state,
+            ctx.accounts.sol_vault.to_account_info().lamports(),
+            gpu_vault_balance,
+            clock.unix_timestamp,
+        )?;
+        let (hash, sol, gpu) = settle_position(position, user_state, global_state, clock.unix_timestamp)?;
+        
+        emit!(PositionHarvested {
+            user: position.owner,
+            position_id: position.id,
+            hash,
+            sol,
+            gpu,
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    /// Return a locked position's MH/s to the owner once its term has ended,
+    /// settling its rewards and closing the position account
+    pub fn unlock_position(ctx: Context<UnlockPosition>) -> Result<()> {
+        let global_state = &mut ctx.accounts.global_state;
+        require_not_paused(global_state, PAUSE_COMPOUND)?;
+        let user_state = &mut ctx.accounts.user_state;
+        let position = &mut ctx.accounts.position;
+        let clock = Clock::get()?;
+        
+        require!(clock.unix_timestamp >= position.unlock_at, ErrorCode::PositionLocked);
+        
+        let gpu_vault_balance = optional_gpu_vault_balance(global_state, ctx.accounts.gpu_vault.as_deref())?;
+        update_pools(
+            global_state,
+            ctx.accounts.sol_vault.to_account_info().lamports(),
+            gpu_vault_balance,
+            clock.unix_timestamp,
+        )?;
+        settle_position(position, user_state, global_state, clock.unix_timestamp)?;
+        settle_user(user_state, global_state, clock.unix_timestamp)?;
+        
+        add_user_power(user_state, position.mining_power, position.rated_mining_power)?;
+        user_state.locked_mining_power = user_state.locked_mining_power
+            .checked_sub(position.rated_mining_power)
+            .ok_or(ErrorCode::Overflow)?;
+        sync_reward_debt(user_state, global_state)?;
+        
+        global_state.total_locked_mhs = global_state.total_locked_mhs
+            .checked_sub(position.mining_power)
+            .ok_or(ErrorCode::Overflow)?;
+        global_state.total_lock_bonus = global_state.total_lock_bonus
+            .checked_sub(position.reward_weight - position.mining_power)
+            .ok_or(ErrorCode::Overflow)?;
+        
+        emit!(PositionUnlocked {
+            user: position.owner,
+            position_id: position.id,
+            mhs_unlocked: position.mining_power,
+            mining_power: user_state.mining_power,
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    /// Restore worn wallet MH/s to its rated power, paying `repair_*_per_mhs`
+    /// per MH/s restored in SOL or GPU. Payment goes into the vault.
+    pub fn repair_rigs(ctx: Context<RepairRigs>, currency: PaymentCurrency) -> Result<()> {
+        let global_state = &mut ctx.accounts.global_state;
+        require!(global_state.initialized, ErrorCode::NotInitialized);
+        require_not_paused(global_state, PAUSE_REPAIRS)?;
+        
+        let user_state = &mut ctx.accounts.user_state;
+        let clock = Clock::get()?;
+        
+        let gpu_vault_balance = optional_gpu_vault_balance(global_state, ctx.accounts.gpu_vault.as_deref())?;
+        update_pools(
+            global_state,
+            ctx.accounts.sol_vault.to_account_info().lamports(),
+            gpu_vault_balance,
+            clock.unix_timestamp,
+        )?;
+        settle_user(user_state, global_state, clock.unix_timestamp)?;
+        
+        let mhs_restored = user_state.rated_mining_power
+            .checked_sub(user_state.mining_power)
+            .ok_or(ErrorCode::Overflow)?;
+        require!(mhs_restored > 0, ErrorCode::NothingToRepair);
+        
+        // A zero price means repairs in that currency are off
+        let price_per_mhs = match currency {
+            PaymentCurrency::Sol => global_state.repair_lamports_per_mhs,
+            PaymentCurrency::Gpu => global_state.repair_gpu_per_mhs,
+        };
+        require!(price_per_mhs > 0, ErrorCode::RepairUnavailable);
+        let cost = mhs_restored.checked_mul(price_per_mhs).ok_or(ErrorCode::Overflow)?;
+        
+        match currency {
+            PaymentCurrency::Sol => {
+                anchor_lang::system_program::transfer(
+                    CpiContext::new(
+                        ctx.accounts.system_program.to_account_info(),
+                        anchor_lang::system_program::Transfer {
+                            from: ctx.accounts.user.to_account_info(),
+                            to: ctx.accounts.sol_vault.to_account_info(),
+                        },
+                    ),
+                    cost,
+                )?;
+            }
+            PaymentCurrency::Gpu => {
+                let (Some(gpu_vault), Some(user_gpu_account)) = (ctx.accounts.gpu_vault.as_ref(), ctx.accounts.user_gpu_account.as_ref()) else {
+                    return err!(ErrorCode::MissingGpuAccounts);
+                };
+                token::transfer(
+                    CpiContext::new(
+                        ctx.accounts.token_program.to_account_info(),
+                        SplTransfer {
+                            from: user_gpu_account.to_account_info(),
+                            to: gpu_vault.to_account_info(),
+                            authority: ctx.accounts.user.to_account_info(),
+                        },
+                    ),
+                    cost,
+                )?;
+            }
+        }
+        
+        user_state.mining_power = user_state.rated_mining_power;
+        sync_reward_debt(user_state, global_state)?;
+        
+        global_state.total_mining_power = global_state.total_mining_power
+            .checked_add(mhs_restored)
+            .ok_or(ErrorCode::Overflow)?;
+        
+        emit!(RigsRepaired {
+            user: user_state.owner,
+            currency,
+            mhs_restored,
+            cost,
+            mining_power: user_state.mining_power,
+            total_mining_power: global_state.total_mining_power,
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    /// Move `mhs_amount` MH/s out of the user account into a new transferable rig
+    /// position, PDA of ["rig", id]. The rig earns pool rewards and hash on its own.
+    pub fn create_rig(ctx: Context<CreateRig>, mhs_amount: u64) -> Result<()> {
+        let global_state = &mut ctx.accounts.global_state;
+        require!(global_state.initialized, ErrorCode::NotInitialized);
+        require_not_paused(global_state, PAUSE_TRANSFERS)?;
+        
+        let user_state = &mut ctx.accounts.user_state;
+        let rig = &mut ctx.accounts.rig;
+        let clock = Clock::get()?;
+        
+        require!(mhs_amount > 0 && mhs_amount <= user_state.mining_power, ErrorCode::InvalidAmount);
+        
+        let gpu_vault_balance = optional_gpu_vault_balance(global_state, ctx.accounts.gpu_vault.as_deref())?;
+        update_pools(
+            global_state,
+            ctx.accounts.sol_vault.to_account_info().lamports(),
+            gpu_vault_balance,
+            clock.unix_timestamp,
+        )?;
+        settle_user(user_state, global_state, clock.unix_timestamp)?;
+        
+        let rated = remove_user_power(user_state, mhs_amount)?;
+        sync_reward_debt(user_state, global_state)?;
+        
+        rig.id = global_state.next_rig_id;
+        rig.owner = ctx.accounts.user.key();
+        rig.nft_mint = None;
+        rig.mining_power = mhs_amount;
+        rig.unclaimed_hash = 0;
+        rig.last_hash_update = clock.unix_timestamp;
+        rig.unclaimed_sol = 0;
+        rig.unclaimed_gpu = 0;
+        rig.created_at = clock.unix_timestamp;
+        rig.bump = ctx.bumps.rig;
+        rig.rated_mining_power = rated;
+        rig.decay_snapshot = global_state.decay_index;
+        sync_rig_debt(rig, global_state)?;
+        
+        // The MH/s only changes hands, total_mining_power is unchanged
+        global_state.total_rig_mhs = global_state.total_rig_mhs
+            .checked_add(mhs_amount)
+            .ok_or(ErrorCode::Overflow)?;
+        global_state.next_rig_id = global_state.next_rig_id
+            .checked_add(1)
+            .ok_or(ErrorCode::Overflow)?;
+        
+        emit!(RigCreated {
+            rig_id: rig.id,
+            owner: rig.owner,
+            mining_power: mhs_amount,
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    /// Back a rig with a 1-of-1 NFT (mint PDA ["rig_mint", rig]). From then on
+    /// whoever holds the NFT controls the rig, and the rig trades as a token.
+    pub fn tokenize_rig(ctx: Context<TokenizeRig>) -> Result<()> {
+        require_not_paused(&ctx.accounts.global_state, PAUSE_TRANSFERS)?;
+        
+        let rig = &ctx.accounts.rig;
+        require!(rig.nft_mint.is_none(), ErrorCode::RigAlreadyTokenized);
+        
+        let rig_id = rig.id.to_le_bytes();
+        let signer_seeds: &[&[&[u8]]] = &[&[b"rig", rig_id.as_ref(), &[rig.bump]]];
+        
+        token::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::MintTo {
+                    mint: ctx.accounts.nft_mint.to_account_info(),
+                    to: ctx.accounts.owner_nft_account.to_account_info(),
+                    authority: ctx.accounts.rig.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            1,
+        )?;
+        
+        // Fix the supply at one
+        token::set_authority(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::SetAuthority {
+                    current_authority: ctx.accounts.rig.to_account_info(),
+                    account_or_mint: ctx.accounts.nft_mint.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            token::spl_token::instruction::AuthorityType::MintTokens,
+            None,
+        )?;
+        
+        let rig = &mut ctx.accounts.rig;
+        rig.nft_mint = Some(ctx.accounts.nft_mint.key());
+        
+        emit!(RigTokenized {
+            rig_id: rig.id,
+            owner: rig.owner,
+            nft_mint: ctx.accounts.nft_mint.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    /// Hand a rig to `new_owner`. Its pending hash and SOL/GPU are settled into the
+    /// current owner's ledgers first. NFT-backed rigs move by transferring the NFT.
+    pub fn transfer_rig(ctx: Context<TransferRig>, new_owner: Pubkey) -> Result<()> {
+        let global_state = &mut ctx.accounts.global_state;
+        require_not_paused(global_state, PAUSE_TRANSFERS)?;
+        
+        let user_state = &mut ctx.accounts.user_state;
+        let rig = &mut ctx.accounts.rig;
+        let clock = Clock::get()?;
+        
+        require!(rig.nft_mint.is_none(), ErrorCode::RigIsTokenized);
+        require!(new_owner != Pubkey::default(), ErrorCode::InvalidAmount);
+        
+        let gpu_vault_balance = optional_gpu_vault_balance(global_state, ctx.accounts.gpu_vault.as_deref())?;
+        update_pools(
+            global_state,
+            ctx.accounts.sol_vault.to_account_info().lamports(),
+            gpu_vault_balance,
+            clock.unix_timestamp,
+        )?;
+        settle_rig(rig, global_state, clock.unix_timestamp)?;
+        let (hash, sol, gpu) = (rig.unclaimed_hash, rig.unclaimed_sol, rig.unclaimed_gpu);
+        move_rig_ledgers(rig, user_state, true)?;
+        
+        let previous_owner = rig.owner;
+        rig.owner = new_owner;
+        
+        emit!(RigTransferred {
+            rig_id: rig.id,
+            from: previous_owner,
+            to: new_owner,
+            settled_hash: hash,
+            settled_sol: sol,
+            settled_gpu: gpu,
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    /// Compound a rig's own hash into more MH/s on the rig
+    pub fn compound_rig(ctx: Context<CompoundRig>) -> Result<()> {
+        let global_state = &mut ctx.accounts.global_state;
+        require_not_paused(global_state, PAUSE_COMPOUND)?;
+        
+        let rig = &mut ctx.accounts.rig;
+        let clock = Clock::get()?;
+        
+        authorize_rig_holder(rig, ctx.accounts.holder.key(), ctx.accounts.holder_nft_account.as_deref())?;
+        
+        let gpu_vault_balance = optional_gpu_vault_balance(global_state, ctx.accounts.gpu_vault.as_deref())?;
+        update_pools(
+            global_state,
+            ctx.accounts.sol_vault.to_account_info().lamports(),
+            gpu_vault_balance,
+            clock.unix_timestamp,
+        )?;
+        settle_rig(rig, global_state, clock.unix_timestamp)?;
+        
+        retarget_difficulty(global_state, clock.unix_timestamp)?;
+        let difficulty = difficulty(global_state);
+        let new_mhs = rig.unclaimed_hash / difficulty;
+        require!(new_mhs > 0, ErrorCode::InvalidAmount);
+        let hash_spent = new_mhs.checked_mul(difficulty).ok_or(ErrorCode::Overflow)?;
+        
+        rig.mining_power = rig.mining_power.checked_add(new_mhs).ok_or(ErrorCode::Overflow)?;
+        rig.rated_mining_power = rig.rated_mining_power.checked_add(new_mhs).ok_or(ErrorCode::Overflow)?;
+        rig.unclaimed_hash = rig.unclaimed_hash.checked_sub(hash_spent).ok_or(ErrorCode::Overflow)?;
+        sync_rig_debt(rig, global_state)?;
+        
+        global_state.total_mining_power = global_state.total_mining_power.checked_add(new_mhs).ok_or(ErrorCode::Overflow)?;
+        global_state.total_rig_mhs = global_state.total_rig_mhs.checked_add(new_mhs).ok_or(ErrorCode::Overflow)?;
+        
+        emit!(RigCompounded {
+            rig_id: rig.id,
+            owner: rig.owner,
+            hash_spent,
+            mhs_added: new_mhs,
+            difficulty,
+            mining_power: rig.mining_power,
+            total_mining_power: global_state.total_mining_power,
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    /// Move a rig's SOL/GPU rewards into the holder's ledgers, to be paid by `claim_earnings`
+    pub fn harvest_rig(ctx: Context<HarvestRig>) -> Result<()> {
+        let global_state = &mut ctx.accounts.global_state;
+        require_not_paused(global_state, PAUSE_COMPOUND)?;
+        let user_state = &mut ctx.accounts.user_state;
+        let rig = &mut ctx.accounts.rig;
+        let clock = Clock::get()?;
+        
+        authorize_rig_holder(rig, ctx.accounts.holder.key(), ctx.accounts.holder_nft_account.as_deref())?;
+        
+        let gpu_vault_balance = optional_gpu_vault_balance(global_state, ctx.accounts.gpu_vault.as_deref())?;
+        update_pools(
+            global_state,
+            ctx.accounts.sol_vault.to_account_info().lamports(),
+            gpu_vault_balance,
+            clock.unix_timestamp,
+        )?;
+        settle_rig(rig, global_state, clock.unix_timestamp)?;
+        let (sol, gpu) = (rig.unclaimed_sol, rig.unclaimed_gpu);
+        move_rig_ledgers(rig, user_state, false)?;
+        
+        emit!(RigHarvested {
+            rig_id: rig.id,
+            owner: rig.owner,
+            sol,
+            gpu,
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    /// Fold a rig back into the holder's user account: its MH/s returns to
+    /// `mining_power` and its banked hash and SOL/GPU move to the holder's ledgers.
+    /// An NFT-backed rig's NFT is burned, and the rig account is closed to the holder.
+    pub fn merge_rig(ctx: Context<MergeRig>) -> Result<()> {
+        let global_state = &mut ctx.accounts.global_state;
+        require_not_paused(global_state, PAUSE_TRANSFERS)?;
+        
+        let user_state = &mut ctx.accounts.user_state;
+        let rig = &mut ctx.accounts.rig;
+        let clock = Clock::get()?;
+        
+        authorize_rig_holder(rig, ctx.accounts.holder.key(), ctx.accounts.holder_nft_account.as_deref())?;
+        
+        let gpu_vault_balance = optional_gpu_vault_balance(global_state, ctx.accounts.gpu_vault.as_deref())?;
+        update_pools(
+            global_state,
+            ctx.accounts.sol_vault.to_account_info().lamports(),
+            gpu_vault_balance,
+            clock.unix_timestamp,
+        )?;
+        settle_user(user_state, global_state, clock.unix_timestamp)?;
+        settle_rig(rig, global_state, clock.unix_timestamp)?;
+        let (hash, sol, gpu) = (rig.unclaimed_hash, rig.unclaimed_sol, rig.unclaimed_gpu);
+        move_rig_ledgers(rig, user_state, true)?;
+        
+        // The MH/s only changes hands, total_mining_power is unchanged
+        let mhs = rig.mining_power;
+        add_user_power(user_state, mhs, rig.rated_mining_power)?;
+        sync_reward_debt(user_state, global_state)?;
+        global_state.total_rig_mhs = global_state.total_rig_mhs
+            .checked_sub(mhs)
+            .ok_or(ErrorCode::Overflow)?;
+        
+        if let Some(nft_mint) = rig.nft_mint {
+            let mint = ctx.accounts.nft_mint.as_ref().ok_or(ErrorCode::MissingRigNft)?;
+            let holder_nft_account = ctx.accounts.holder_nft_account.as_ref().ok_or(ErrorCode::MissingRigNft)?;
+            require_keys_eq!(mint.key(), nft_mint, ErrorCode::MissingRigNft);
+            
+            token::burn(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    token::Burn {
+                        mint: mint.to_account_info(),
+                        from: holder_nft_account.to_account_info(),
+                        authority: ctx.accounts.holder.to_account_info(),
+                    },
+                ),
+                1,
+            )?;
+        }
+        
+        emit!(RigMerged {
+            rig_id: rig.id,
+            owner: rig.owner,
+            mining_power: mhs,
+            settled_hash: hash,
+            settled_sol: sol,
+            settled_gpu: gpu,
+            user_mining_power: user_state.mining_power,
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    /// Claim fee-funded referral rewards in SOL and GPU (no protocol fee, it already paid for them)
+    pub fn claim_referral_rewards(ctx: Context<ClaimReferralRewards>) -> Result<()> {
+        let global_state = &mut ctx.accounts.global_state;
+        let user_state = &mut ctx.accounts.user_state;
+        
+        // Referral rewards follow the SOL/GPU claim pause bits
+        let sol_claims_open = global_state.paused & PAUSE_SOL_CLAIMS == 0;
+        let gpu_claims_open = global_state.paused & PAUSE_GPU_CLAIMS == 0;
+        require!(sol_claims_open || gpu_claims_open, ErrorCode::Paused);
+        
+        let sol_amount = if sol_claims_open { user_state.referral_sol_earned } else { 0 };
+        let gpu_amount = if gpu_claims_open { user_state.referral_gpu_earned } else { 0 };
+        require!(sol_amount > 0 || gpu_amount > 0, ErrorCode::InvalidAmount);
+        
+        if sol_amount > 0 {
+            require!(ctx.accounts.sol_vault.lamports() >= sol_amount, ErrorCode::InsufficientFunds);
+            
+            user_state.referral_sol_earned = 0;
+            global_state.total_unclaimed_sol = global_state.total_unclaimed_sol.saturating_sub(sol_amount);
+            
+            let vault_bump = ctx.bumps.sol_vault;
+            let signer_seeds: &[&[&[u8]]] = &[&[b"vault", &[vault_bump]]];
+            
+            anchor_lang::system_program::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.sol_vault.to_account_info(),
+                        to: ctx.accounts.user.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                sol_amount,
+            )?;
+        }
+        
+        if gpu_amount > 0 {
+            let (Some(gpu_vault), Some(user_gpu_account)) = (ctx.accounts.gpu_vault.as_ref(), ctx.accounts.user_gpu_account.as_ref()) else {
+                return err!(ErrorCode::MissingGpuAccounts);
+            };
+            require!(gpu_vault.amount >= gpu_amount, ErrorCode::InsufficientFunds);
+            
+            user_state.referral_gpu_earned = 0;
+            global_state.total_unclaimed_gpu = global_state.total_unclaimed_gpu.saturating_sub(gpu_amount);
+            
+            let gpu_vault_bump = ctx.bumps.gpu_vault_authority;
+            let gpu_signer_seeds: &[&[&[u8]]] = &[&[b"gpu_vault", &[gpu_vault_bump]]];
+            
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    SplTransfer {
+                        from: gpu_vault.to_account_info(),
+                        to: user_gpu_account.to_account_info(),
+                        authority: ctx.accounts.gpu_vault_authority.to_account_info(),
+                    },
+                    gpu_signer_seeds,
+                ),
+                gpu_amount,
+            )?;
+        }
+        
+        emit!(ReferralRewardsClaimed {
+            user: ctx.accounts.user.key(),
+            sol_amount,
+            gpu_amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    /// Initialize user account
+    pub fn init_user(ctx: Context<InitUser>) -> Result<()> {
+        let user_state = &mut ctx.accounts.user_state;
+        let clock = Clock::get()?;
+        
+        reset_user_state(user_state, ctx.accounts.user.key(), clock.unix_timestamp);
+        
+        emit!(UserInitialized {
+            user: user_state.owner,
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    /// Migrate a user account created before the hash ledger was split out.
+    /// Grows the account to the current layout. The legacy slots line up with the
+    /// new ledgers: `unclaimed_gpu_earnings` becomes `unclaimed_gpu`, and
+    /// `unclaimed_earnings` becomes `unclaimed_sol`. The legacy claim and compound
+    /// both zeroed `unclaimed_earnings`, so it is normally empty; anything left
+    /// there was already reserved in `total_unclaimed_sol`, so SOL is the only
+    /// ledger it can move to without unbalancing the reserve. Hash the old layout
+    /// never banked is picked up from `last_claim` below.
+    pub fn migrate_user_state(ctx: Context<MigrateUserState>) -> Result<()> {
+        let user_state_info = ctx.accounts.user_state.to_account_info();
+        
+        grow_account(
+            &user_state_info,
+            &ctx.accounts.user.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            8 + UserState::INIT_SPACE,
+        )?;
+        
+        let mut user_state = UserState::try_deserialize(&mut &user_state_info.try_borrow_data()?[..])?;
+        require!(user_state.owner == ctx.accounts.user.key(), ErrorCode::InvalidOwner);
+        
+        // Legacy accounts never tracked a separate hash clock
+        if user_state.last_hash_update == 0 {
+            user_state.last_hash_update = user_state.last_claim;
+        }
+        // Reward debts stay zero: the indices started at zero when the global state
+        // was migrated and a legacy account's power cannot have moved since
+        
+        user_state.try_serialize(&mut &mut user_state_info.try_borrow_mut_data()?[..])?;
+        
+        emit!(UserStateMigrated {
+            user: user_state.owner,
+            size: user_state_info.data_len() as u32,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    /// Admin: Create the protocol treasury PDA and its GPU token account.
+    /// Not timelocked: it only creates accounts and moves no funds.
+    pub fn init_treasury(ctx: Context<InitTreasury>) -> Result<()> {
+        ctx.accounts.treasury.bump = ctx.bumps.treasury;
+        
+        emit!(TreasuryInitialized {
+            treasury: ctx.accounts.treasury.key(),
+            treasury_gpu_account: ctx.accounts.treasury_gpu_account.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    /// Burn every GPU token sitting in the treasury (permissionless)
+    pub fn burn_gpu(ctx: Context<BurnGpu>) -> Result<()> {
+        let amount = ctx.accounts.treasury_gpu_account.amount;
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        
+        let treasury_bump = ctx.accounts.treasury.bump;
+        let signer_seeds: &[&[&[u8]]] = &[&[b"treasury", &[treasury_bump]]];
+        
+        token::burn(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Burn {
+                    mint: ctx.accounts.gpu_token_mint.to_account_info(),
+                    from: ctx.accounts.treasury_gpu_account.to_account_info(),
+                    authority: ctx.accounts.treasury.to_account_info(),
+                },
+                signer_seeds,
+            ),
+            amount,
+        )?;
+        
+        let global_state = &mut ctx.accounts.global_state;
+        global_state.total_gpu_burned = global_state.total_gpu_burned
+            .checked_add(amount)
+            .ok_or(ErrorCode::Overflow)?;
+        
+        emit!(GpuBurned {
+            amount,
+            total_gpu_burned: global_state.total_gpu_burned,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    /// Admin: Queue a fund or parameter change behind the timelock. The proposal
+    /// account is public, so users see `description` and the action before it can run.
+    pub fn propose_admin_action(ctx: Context<ProposeAdminAction>, action: AdminAction, description: String) -> Result<()> {
+        require!(description.len() <= MAX_PROPOSAL_DESCRIPTION_LEN, ErrorCode::DescriptionTooLong);
+        let clock = Clock::get()?;
+        validate_admin_action(&action, clock.unix_timestamp)?;
+        
+        let global_state = &mut ctx.accounts.global_state;
+        let proposal = &mut ctx.accounts.proposal;
+        
+        let delay = timelock_delay(global_state);
+        
+        proposal.id = global_state.next_proposal_id;
+        proposal.proposer = ctx.accounts.authority.key();
+        proposal.action = action.clone();
+        proposal.description = description.clone();
+        proposal.created_at = clock.unix_timestamp;
+        proposal.eta = clock.unix_timestamp.checked_add(delay as i64).ok_or(ErrorCode::Overflow)?;
+        proposal.status = ProposalStatus::Pending;
+        proposal.bump = ctx.bumps.proposal;
+        
+        global_state.next_proposal_id = global_state.next_proposal_id
+            .checked_add(1)
+            .ok_or(ErrorCode::Overflow)?;
+        
+        emit!(ProposalCreated {
+            id: proposal.id,
+            proposer: proposal.proposer,
+            action,
+            description,
+            eta: proposal.eta,
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    /// Admin: Queue an `UpdateConfig` proposal for the core economic parameters.
+    /// Like every parameter change since the timelock was added, the values only
+    /// apply once `execute_proposal` runs after the delay; the bounds are checked
+    /// at both steps.
+    pub fn update_config(
+        ctx: Context<ProposeAdminAction>,
+        daily_pool_percentage: u8,
+        protocol_fee_val: u8,
+        gpu_penalty_bps: u16,
+        base_buy_rate: u64,
+        description: String,
+    ) -> Result<()> {
+        let action = AdminAction::UpdateConfig { daily_pool_percentage, protocol_fee_val, gpu_penalty_bps, base_buy_rate };
+        
+        propose_admin_action(ctx, action, description)
+    }
+
+    /// Admin: Run a pending proposal once its timelock has passed
+    pub fn execute_proposal(ctx: Context<ExecuteProposal>) -> Result<()> {
+        let proposal = &mut ctx.accounts.proposal;
+        let clock = Clock::get()?;
+        
+        require!(proposal.status == ProposalStatus::Pending, ErrorCode::ProposalNotPending);
+        require!(clock.unix_timestamp >= proposal.eta, ErrorCode::ProposalNotReady);
+        
+        proposal.status = ProposalStatus::Executed;
+        let action = proposal.action.clone();
+        
+        // Re-check in case the rules moved while the proposal was queued
+        validate_admin_action(&action, clock.unix_timestamp)?;
+        
+        match action {
+            AdminAction::DrainVault { amount } => {
+                let vault_bump = ctx.bumps.vault;
+                let signer_seeds: &[&[&[u8]]] = &[&[b"vault", &[vault_bump]]];
+                
+                anchor_lang::system_program::transfer(
+                    CpiContext::new_with_signer(
+                        ctx.accounts.system_program.to_account_info(),
+                        anchor_lang::system_program::Transfer {
+                            from: ctx.accounts.vault.to_account_info(),
+                            to: ctx.accounts.authority.to_account_info(),
+                        },
+                        signer_seeds,
+                    ),
+                    amount,
+                )?;
+                
+                emit!(VaultDrained {
+                    authority: ctx.accounts.authority.key(),
+                    amount,
+                    vault_balance: ctx.accounts.vault.lamports(),
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+            AdminAction::SetGpuToken { gpu_token_mint } => {
+                ctx.accounts.global_state.gpu_token_mint = gpu_token_mint;
+                
+                emit!(GpuTokenUpdated {
+                    gpu_token_mint,
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+            AdminAction::SetTimelockDelay { delay_secs } => {
+                ctx.accounts.global_state.timelock_delay_secs = delay_secs;
+                
+                emit!(TimelockDelayUpdated {
+                    delay_secs,
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+            AdminAction::SetBuybackWallet { buyback_wallet } => {
+                ctx.accounts.global_state.buyback_wallet = buyback_wallet;
+                
+                emit!(BuybackWalletUpdated {
+                    buyback_wallet,
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+            AdminAction::WithdrawBuybackSol { amount } => {
+                // Treasury SOL only ever leaves for the fixed buyback wallet; the GPU
+                // bought with it goes back to the treasury and is burned by burn_gpu
+                let global_state = &mut ctx.accounts.global_state;
+                require!(global_state.buyback_wallet != Pubkey::default(), ErrorCode::BuybackWalletNotSet);
+                let (Some(treasury), Some(buyback_wallet)) = (
+                    ctx.accounts.treasury.as_ref(),
+                    ctx.accounts.buyback_wallet.as_ref(),
+                ) else {
+                    return err!(ErrorCode::MissingProposalAccounts);
+                };
+                
+                // The treasury keeps enough lamports to stay rent exempt
+                let treasury_info = treasury.to_account_info();
+                let rent_floor = Rent::get()?.minimum_balance(treasury_info.data_len());
+                let available = treasury_info.lamports().saturating_sub(rent_floor);
+                require!(amount <= available, ErrorCode::InsufficientFunds);
+                
+                treasury_info.sub_lamports(amount)?;
+                buyback_wallet.add_lamports(amount)?;
+                
+                global_state.total_buyback_sol = global_state.total_buyback_sol
+                    .checked_add(amount)
+                    .ok_or(ErrorCode::Overflow)?;
+                
+                emit!(BuybackSolWithdrawn {
+                    buyback_wallet: buyback_wallet.key(),
+                    amount,
+                    total_buyback_sol: global_state.total_buyback_sol,
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+            AdminAction::ResetUserPower { user } => {
+                let global_state = &mut ctx.accounts.global_state;
+                let user_state = ctx.accounts.user_state.as_mut().ok_or(ErrorCode::MissingProposalAccounts)?;
+                require_keys_eq!(user_state.owner, user, ErrorCode::InvalidOwner);
+                
+                // Bank rewards up to now so they can be released below
+                let gpu_vault_balance = optional_gpu_vault_balance(global_state, ctx.accounts.gpu_vault.as_deref())?;
+                update_pools(global_state, ctx.accounts.vault.lamports(), gpu_vault_balance, clock.unix_timestamp)?;
+                settle_user(user_state, global_state, clock.unix_timestamp)?;
+                
+                // Everything the wallet has reserved goes back: earnings, referral
+                // rewards and queued sell proceeds
+                let old_power = user_state.mining_power;
+                let released_sol = user_state.unclaimed_sol
+                    .checked_add(user_state.referral_sol_earned)
+                    .and_then(|sol| sol.checked_add(user_state.pending_sell_sol))
+                    .ok_or(ErrorCode::Overflow)?;
+                let released_gpu = user_state.unclaimed_gpu
+                    .checked_add(user_state.referral_gpu_earned)
+                    .and_then(|gpu| gpu.checked_add(user_state.pending_sell_gpu))
+                    .ok_or(ErrorCode::Overflow)?;
+                
+                // Subtract from global total
+                global_state.total_mining_power = global_state.total_mining_power
+                    .checked_sub(old_power)
+                    .ok_or(ErrorCode::Overflow)?;
+                
+                // Release the user's pending SOL/GPU back into the mineable TVL
+                global_state.total_unclaimed_sol = global_state.total_unclaimed_sol.saturating_sub(released_sol);
+                global_state.total_unclaimed_gpu = global_state.total_unclaimed_gpu.saturating_sub(released_gpu);
+                
+                user_state.mining_power = 0;
+                user_state.rated_mining_power = 0;
+                user_state.unclaimed_sol = 0;
+                user_state.unclaimed_gpu = 0;
+                user_state.unclaimed_hash = 0;
+                user_state.sol_reward_debt = 0;
+                user_state.gpu_reward_debt = 0;
+                user_state.referral_sol_earned = 0;
+                user_state.referral_gpu_earned = 0;
+                user_state.pending_sell_sol = 0;
+                user_state.pending_sell_gpu = 0;
+                user_state.sell_unlock_at = 0;
+                
+                emit!(UserPowerReset {
+                    user,
+                    old_power,
+                    released_sol,
+                    released_gpu,
+                    total_mining_power: global_state.total_mining_power,
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+            AdminAction::ForcePrices { sol_usd_price, gpu_usd_price } => {
+                set_prices(&mut ctx.accounts.global_state, sol_usd_price, gpu_usd_price, true, &clock);
+            }
+            AdminAction::SetMaxPriceMove { max_price_move_bps } => {
+                ctx.accounts.global_state.max_price_move_bps = max_price_move_bps;
+                
+                emit!(MaxPriceMoveUpdated {
+                    max_price_move_bps,
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+            AdminAction::SetPriceOracle {
+                price_source,
+                sol_price_feed,
+                gpu_price_feed,
+                max_price_age_secs,
+                max_confidence_bps,
+                manual_max_age_secs,
+            } => {
+                let global_state = &mut ctx.accounts.global_state;
+                global_state.price_source = price_source;
+                global_state.sol_price_feed = sol_price_feed;
+                global_state.gpu_price_feed = gpu_price_feed;
+                global_state.max_price_age_secs = max_price_age_secs;
+                global_state.max_confidence_bps = max_confidence_bps;
+                global_state.manual_price_max_age_secs = manual_max_age_secs;
+                
+                emit!(PriceOracleUpdated {
+                    price_source,
+                    sol_price_feed,
+                    gpu_price_feed,
+                    max_price_age_secs,
+                    max_confidence_bps,
+                    manual_max_age_secs,
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+            AdminAction::SetFeeSplit { shares } => {
+                let global_state = &mut ctx.accounts.global_state;
+                global_state.fee_split_count = shares.len() as u8;
+                global_state.fee_split = [FeeShare::default(); MAX_FEE_RECIPIENTS];
+                global_state.fee_split[..shares.len()].copy_from_slice(&shares);
+                
+                emit!(FeeSplitUpdated {
+                    shares,
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+            AdminAction::SetSellParams { exit_fee_bps, cooldown_secs } => {
+                let global_state = &mut ctx.accounts.global_state;
+                global_state.exit_fee_bps = exit_fee_bps;
+                global_state.sell_cooldown_secs = cooldown_secs;
+                
+                emit!(SellParamsUpdated {
+                    exit_fee_bps,
+                    cooldown_secs,
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+            AdminAction::SetDevWallet { dev_wallet } => {
+                let global_state = &mut ctx.accounts.global_state;
+                let previous_dev_wallet = global_state.dev_wallet;
+                global_state.dev_wallet = dev_wallet;
+                
+                emit!(DevWalletUpdated {
+                    previous_dev_wallet,
+                    dev_wallet,
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+            AdminAction::SetReferralSchedule { level_bps } => {
+                let global_state = &mut ctx.accounts.global_state;
+                global_state.referral_levels = level_bps.len() as u8;
+                global_state.referral_bps = [0; MAX_REFERRAL_LEVELS];
+                global_state.referral_bps[..level_bps.len()].copy_from_slice(&level_bps);
+                
+                emit!(ReferralScheduleUpdated {
+                    level_bps,
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+            AdminAction::SetReferralRewardMode { mode } => {
+                ctx.accounts.global_state.referral_reward_mode = mode;
+                
+                emit!(ReferralRewardModeUpdated {
+                    mode,
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+            AdminAction::SetGenesisThresholds { mhs_threshold, tvl_threshold_usd } => {
+                let global_state = &mut ctx.accounts.global_state;
+                global_state.genesis_mhs_threshold = mhs_threshold;
+                global_state.genesis_tvl_threshold_usd = tvl_threshold_usd;
+                
+                emit!(GenesisThresholdsUpdated {
+                    mhs_threshold,
+                    tvl_threshold_usd,
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+            AdminAction::SetTransferFee { transfer_fee_bps } => {
+                ctx.accounts.global_state.transfer_fee_bps = transfer_fee_bps;
+                
+                emit!(TransferFeeUpdated {
+                    transfer_fee_bps,
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+            AdminAction::SetDecayParams { decay_bps_per_day, repair_lamports_per_mhs, repair_gpu_per_mhs } => {
+                // Wear up to now is applied at the old rate
+                let global_state = &mut ctx.accounts.global_state;
+                let gpu_vault_balance = optional_gpu_vault_balance(global_state, ctx.accounts.gpu_vault.as_deref())?;
+                update_pools(global_state, ctx.accounts.vault.lamports(), gpu_vault_balance, clock.unix_timestamp)?;
+                
+                global_state.decay_bps_per_day = decay_bps_per_day;
+                global_state.repair_lamports_per_mhs = repair_lamports_per_mhs;
+                global_state.repair_gpu_per_mhs = repair_gpu_per_mhs;
+                
+                emit!(DecayParamsUpdated {
+                    decay_bps_per_day,
+                    repair_lamports_per_mhs,
+                    repair_gpu_per_mhs,
+                    decay_index: global_state.decay_index,
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+            AdminAction::SetDifficultyParams { epoch_secs, target_growth_bps } => {
+                // Start a fresh epoch under the new parameters
+                let global_state = &mut ctx.accounts.global_state;
+                global_state.difficulty = difficulty(global_state);
+                global_state.difficulty_epoch_secs = epoch_secs;
+                global_state.target_growth_bps = target_growth_bps;
+                global_state.difficulty_epoch_start = clock.unix_timestamp;
+                global_state.epoch_start_mining_power = full_condition_hashrate(global_state)?;
+                
+                emit!(DifficultyParamsUpdated {
+                    epoch_secs,
+                    target_growth_bps,
+                    difficulty: global_state.difficulty,
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+            AdminAction::SetEmissionSchedule { start, epoch_secs, decay_bps } => {
+                // Rewards up to now are released under the old schedule
+                let global_state = &mut ctx.accounts.global_state;
+                let gpu_vault_balance = optional_gpu_vault_balance(global_state, ctx.accounts.gpu_vault.as_deref())?;
+                update_pools(global_state, ctx.accounts.vault.lamports(), gpu_vault_balance, clock.unix_timestamp)?;
+                
+                global_state.emission_start = start;
+                global_state.emission_epoch_secs = epoch_secs;
+                global_state.emission_decay_bps = decay_bps;
+                
+                emit!(EmissionScheduleUpdated {
+                    start,
+                    epoch_secs,
+                    decay_bps,
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+            AdminAction::UpdateConfig { daily_pool_percentage, protocol_fee_val, gpu_penalty_bps, base_buy_rate } => {
+                // Pool rewards are released at the old daily rate before it changes
+                let global_state = &mut ctx.accounts.global_state;
+                let gpu_vault_balance = optional_gpu_vault_balance(global_state, ctx.accounts.gpu_vault.as_deref())?;
+                update_pools(global_state, ctx.accounts.vault.lamports(), gpu_vault_balance, clock.unix_timestamp)?;
+                
+                global_state.daily_pool_percentage = daily_pool_percentage;
+                global_state.protocol_fee_val = protocol_fee_val;
+                global_state.gpu_penalty_bps = gpu_penalty_bps;
+                global_state.base_buy_rate = base_buy_rate;
+                
+                emit!(ConfigUpdated {
+                    daily_pool_percentage,
+                    protocol_fee_val,
+                    gpu_penalty_bps,
+                    base_buy_rate,
+                    timestamp: clock.unix_timestamp,
+                });
+            }
+        }
+        
+        emit!(ProposalExecuted {
+            id: proposal.id,
+            action: proposal.action.clone(),
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    /// Admin: Cancel a pending proposal
+    pub fn cancel_proposal(ctx: Context<CancelProposal>) -> Result<()> {
+        let proposal = &mut ctx.accounts.proposal;
+        
+        require!(proposal.status == ProposalStatus::Pending, ErrorCode::ProposalNotPending);
+        
+        proposal.status = ProposalStatus::Cancelled;
+        
+        emit!(ProposalCancelled {
+            id: proposal.id,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    /// Admin: Update price oracle (SOL and GPU prices in USD with 8 decimals).
+    /// Not timelocked: routine updates may move each price by at most
+    /// `max_price_move_bps` from where it stood at the start of the current
+    /// `PRICE_MOVE_WINDOW_SECS` window, however many calls are made. Anything
+    /// larger needs a `ForcePrices` proposal.
+    pub fn update_prices(ctx: Context<UpdatePrices>, sol_usd_price: u64, gpu_usd_price: u64) -> Result<()> {
+        let global_state = &mut ctx.accounts.global_state;
+        let clock = Clock::get()?;
+        
+        require!(sol_usd_price > 0, ErrorCode::InvalidAmount);
+        require!(gpu_usd_price > 0, ErrorCode::InvalidAmount);
+        
+        check_manual_prices(global_state, sol_usd_price, gpu_usd_price, clock.unix_timestamp)?;
+        
+        set_prices(global_state, sol_usd_price, gpu_usd_price, false, &clock);
+        
+        Ok(())
+    }
+
+    /// Admin: Start a two-step authority transfer; `new_authority` must call `accept_authority`.
+    /// Not timelocked: the new authority still waits out the timelock on every action.
+    pub fn propose_authority(ctx: Context<ProposeAuthority>, new_authority: Pubkey) -> Result<()> {
+        require!(new_authority != Pubkey::default(), ErrorCode::InvalidAuthority);
+        
+        let global_state = &mut ctx.accounts.global_state;
+        
+        global_state.pending_authority = Some(new_authority);
+        
+        emit!(AuthorityProposed {
+            authority: global_state.authority,
+            pending_authority: new_authority,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    /// Pending authority: Take over as authority
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let global_state = &mut ctx.accounts.global_state;
+        let previous_authority = global_state.authority;
+        
+        global_state.authority = ctx.accounts.new_authority.key();
+        global_state.pending_authority = None;
+        
+        emit!(AuthorityTransferred {
+            previous_authority,
+            new_authority: global_state.authority,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    /// Admin: Give up the authority for good. Every admin-only instruction,
+    /// including queued proposals, becomes unreachable. Nothing may be paused, and
+    /// the pauser role is cleared so no one can pause the protocol afterwards.
+    pub fn renounce_authority(ctx: Context<RenounceAuthority>) -> Result<()> {
+        let global_state = &mut ctx.accounts.global_state;
+        let previous_authority = global_state.authority;
+        let clock = Clock::get()?;
+        
+        require!(global_state.paused == 0, ErrorCode::RenounceWhilePaused);
+        
+        global_state.authority = Pubkey::default();
+        global_state.pending_authority = None;
+        global_state.pauser = Pubkey::default();
+        
+        emit!(PauserUpdated {
+            pauser: Pubkey::default(),
+            timestamp: clock.unix_timestamp,
+        });
+        emit!(AuthorityTransferred {
+            previous_authority,
+            new_authority: Pubkey::default(),
+            timestamp: clock.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    /// Pauser or admin: Replace the pause bitmask (PAUSE_* bits; 0 resumes everything).
+    /// Not timelocked: pausing is the emergency brake and moves no funds.
+    pub fn set_paused(ctx: Context<SetPaused>, paused: u8) -> Result<()> {
+        // Every bit of the u8 is a PAUSE_* flag, so any value is valid
+        let global_state = &mut ctx.accounts.global_state;
+        
+        global_state.paused = paused;
+        
+        emit!(PauseUpdated {
+            paused,
+            by: ctx.accounts.signer.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    /// Admin: Appoint the pauser role (Pubkey::default() leaves pausing to the authority).
+    /// Not timelocked, so a lost or compromised pauser can be replaced during an emergency.
+    pub fn set_pauser(ctx: Context<SetPauser>, pauser: Pubkey) -> Result<()> {
+        let global_state = &mut ctx.accounts.global_state;
+        
+        global_state.pauser = pauser;
+        
+        emit!(PauserUpdated {
+            pauser,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+
+    /// View: MH/s a deposit of `amount` would buy right now (simulate and read return data)
+    pub fn get_mhs_quote(ctx: Context<QuoteView>, amount: u64, currency: PaymentCurrency) -> Result<MhsQuote> {
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        
+        // Quote with the prices a buy would see, without writing them back
+        let mut global_state = (*ctx.accounts.global_state).clone();
+        let gpu_vault_balance = optional_gpu_vault_balance(&global_state, ctx.accounts.gpu_vault.as_deref())?;
+        refresh_prices(
+            &mut global_state,
+            ctx.accounts.sol_price_feed.as_deref(),
+            ctx.accounts.gpu_price_feed.as_deref(),
+            currency,
+            gpu_vault_balance,
+            &Clock::get()?,
+        )?;
+        
+        // SOL buys are priced after the deposit lands in the vault
+        let sol_vault_balance = ctx.accounts.sol_vault.to_account_info().lamports();
+        let priced_sol_balance = match currency {
+            PaymentCurrency::Sol => sol_vault_balance.checked_add(amount).ok_or(ErrorCode::Overflow)?,
+            PaymentCurrency::Gpu => sol_vault_balance,
+        };
+        
+        quote_mhs(&global_state, currency, amount, priced_sol_balance, gpu_vault_balance)
+    }
+
+    /// View: hash, SOL and GPU a user could compound or claim right now
+    pub fn get_pending_rewards(ctx: Context<UserView>) -> Result<PendingRewards> {
+        let (_, user_state) = simulate_settlement(ctx.accounts)?;
+        
+        Ok(PendingRewards {
+            hash: user_state.unclaimed_hash,
+            sol: user_state.unclaimed_sol,
+            gpu: user_state.unclaimed_gpu,
+        })
+    }
+
+    /// View: what `claim_earnings` would pay out right now, and the protocol fee taken
+    pub fn get_claim_preview(ctx: Context<UserView>) -> Result<ClaimPreview> {
+        let (global_state, user_state) = simulate_settlement(ctx.accounts)?;
+        
+        let (sol_fee, sol_payout) = split_protocol_fee(user_state.unclaimed_sol, global_state.protocol_fee_val)?;
+        let (gpu_fee, gpu_payout) = split_protocol_fee(user_state.unclaimed_gpu, global_state.protocol_fee_val)?;
+        
+        Ok(ClaimPreview {
+            sol_gross: user_state.unclaimed_sol,
+            sol_fee,
+            sol_payout,
+            gpu_gross: user_state.unclaimed_gpu,
+            gpu_fee,
+            gpu_payout,
+        })
+    }
+
+    /// Admin: Grow the global state account to the current layout after an upgrade
+    pub fn migrate_global_state(ctx: Context<MigrateGlobalState>) -> Result<()> {
+        let global_state_info = ctx.accounts.global_state.to_account_info();
+        
+        grow_account(
+            &global_state_info,
+            &ctx.accounts.authority.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            8 + GlobalState::INIT_SPACE,
+        )?;
+        
+        let global_state = GlobalState::try_deserialize(&mut &global_state_info.try_borrow_data()?[..])?;
+        require!(global_state.authority == ctx.accounts.authority.key(), ErrorCode::Unauthorized);
+        
+        emit!(GlobalStateMigrated {
+            size: global_state_info.data_len() as u32,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        
+        Ok(())
+    }
+}
+
+// GPU vault balance for an instruction that may run before a GPU token is set:
+// 0 while the mint is unset, otherwise the canonical vault must be passed
+fn optional_gpu_vault_balance(global_state: &GlobalState, gpu_vault: Option<&TokenAccount>) -> Result<u64> {
+    if global_state.gpu_token_mint == Pubkey::default() {
+        return Ok(0);
+    }
+    
+    gpu_vault
+        .map(|gpu_vault| gpu_vault.amount)
+        .ok_or(ErrorCode::MissingGpuAccounts.into())
+}
+
+// Treasury for an instruction that may run before a GPU token is set: init_treasury
+// needs the mint, so the treasury may be omitted until one is set
+fn optional_treasury<'info>(
+    global_state: &GlobalState,
+    treasury: Option<&Account<'info, Treasury>>,
+) -> Result<Option<AccountInfo<'info>>> {
+    let treasury = treasury.map(|treasury| treasury.to_account_info());
+    if global_state.gpu_token_mint == Pubkey::default() {
+        return Ok(treasury);
+    }
+    
+    treasury.map(Some).ok_or(ErrorCode::MissingTreasury.into())
+}
+
+// Fail with `Paused` while any of `flags` is set
+fn require_not_paused(global_state: &GlobalState, flags: u8) -> Result<()> {
+    require!(global_state.paused & flags == 0, ErrorCode::Paused);
+    
+    Ok(())
+}
+
+// Reject proposals that could never execute
+fn validate_admin_action(action: &AdminAction, now: i64) -> Result<()> {
+    match action {
+        AdminAction::DrainVault { amount } => require!(*amount > 0, ErrorCode::InvalidAmount),
+        AdminAction::SetGpuToken { gpu_token_mint } => {
+            require!(*gpu_token_mint != Pubkey::default(), ErrorCode::InvalidAmount)
+        }
+        AdminAction::SetTimelockDelay { delay_secs } => {
+            require!((MIN_TIMELOCK_DELAY_SECS..=MAX_TIMELOCK_DELAY_SECS).contains(delay_secs), ErrorCode::InvalidTimelockDelay)
+        }
+        AdminAction::SetBuybackWallet { buyback_wallet } => {
+            require!(*buyback_wallet != Pubkey::default(), ErrorCode::InvalidRecipient)
+        }
+        AdminAction::WithdrawBuybackSol { amount } => require!(*amount > 0, ErrorCode::InvalidAmount),
+        AdminAction::ResetUserPower { .. } | AdminAction::SetMaxPriceMove { .. } | AdminAction::SetReferralRewardMode { .. } => {}
+        AdminAction::ForcePrices { sol_usd_price, gpu_usd_price } => {
+            require!(*sol_usd_price > 0 && *gpu_usd_price > 0, ErrorCode::InvalidAmount)
+        }
+        AdminAction::SetPriceOracle {
+            price_source,
+            sol_price_feed,
+            gpu_price_feed,
+            max_price_age_secs,
+            max_confidence_bps,
+            ..
+        } => {
+            if *price_source == PriceSource::Pyth {
+                require!(*sol_price_feed != Pubkey::default(), ErrorCode::InvalidPriceFeed);
+                require!(*gpu_price_feed != Pubkey::default(), ErrorCode::InvalidPriceFeed);
+            }
+            require!(*max_price_age_secs > 0, ErrorCode::InvalidAmount);
+            require!(*max_confidence_bps > 0 && *max_confidence_bps <= 10_000, ErrorCode::InvalidAmount);
+        }
+        AdminAction::SetFeeSplit { shares } => {
+            require!(!shares.is_empty() && shares.len() <= MAX_FEE_RECIPIENTS, ErrorCode::InvalidFeeSplit);
+            require!(shares.iter().all(|share| share.recipient != Pubkey::default()), ErrorCode::InvalidFeeSplit);
+            let total_bps = shares.iter().try_fold(0u16, |total, share| total.checked_add(share.bps))
+                .ok_or(ErrorCode::InvalidFeeSplit)?;
+            require!(total_bps == 10_000, ErrorCode::InvalidFeeSplit);
+        }
+        AdminAction::SetSellParams { exit_fee_bps, cooldown_secs } => {
+            require!(*exit_fee_bps > 0 && *exit_fee_bps <= MAX_EXIT_FEE_BPS, ErrorCode::ConfigOutOfBounds);
+            require!(*cooldown_secs <= MAX_SELL_COOLDOWN_SECS, ErrorCode::ConfigOutOfBounds);
+        }
+        AdminAction::SetDevWallet { dev_wallet } => require!(*dev_wallet != Pubkey::default(), ErrorCode::InvalidAmount),
+        AdminAction::SetReferralSchedule { level_bps } => {
+            require!(!level_bps.is_empty() && level_bps.len() <= MAX_REFERRAL_LEVELS, ErrorCode::InvalidReferralSchedule);
+            let total_bps = level_bps.iter().try_fold(0u16, |total, bps| total.checked_add(*bps))
+                .ok_or(ErrorCode::InvalidReferralSchedule)?;
+            require!(total_bps <= MAX_REFERRAL_TOTAL_BPS, ErrorCode::InvalidReferralSchedule);
+        }
+        AdminAction::SetGenesisThresholds { mhs_threshold, tvl_threshold_usd } => {
+            require!(*mhs_threshold <= MAX_GENESIS_MHS_THRESHOLD, ErrorCode::ConfigOutOfBounds);
+            require!(*tvl_threshold_usd <= MAX_GENESIS_TVL_THRESHOLD_USD, ErrorCode::ConfigOutOfBounds);
+        }
+        AdminAction::SetTransferFee { transfer_fee_bps } => {
+            require!(*transfer_fee_bps <= MAX_TRANSFER_FEE_BPS, ErrorCode::ConfigOutOfBounds)
+        }
+        AdminAction::SetDecayParams { decay_bps_per_day, .. } => {
+            require!(*decay_bps_per_day <= MAX_DECAY_BPS_PER_DAY, ErrorCode::ConfigOutOfBounds)
+        }
+        AdminAction::SetDifficultyParams { epoch_secs, target_growth_bps } => {
+            require!(
+                *epoch_secs == 0 || (MIN_DIFFICULTY_EPOCH_SECS..=MAX_DIFFICULTY_EPOCH_SECS).contains(epoch_secs),
+                ErrorCode::ConfigOutOfBounds
+            );
+            require!(*target_growth_bps <= MAX_TARGET_GROWTH_BPS, ErrorCode::ConfigOutOfBounds);
+        }
+        AdminAction::SetEmissionSchedule { start, epoch_secs, decay_bps } => require!(
+            *epoch_secs == 0
+                || ((MIN_EMISSION_EPOCH_SECS..=MAX_EMISSION_EPOCH_SECS).contains(epoch_secs)
+                    && *start >= now
+                    && *decay_bps > 0
+                    && *decay_bps <= 10_000),
+            ErrorCode::ConfigOutOfBounds
+        ),
+        AdminAction::UpdateConfig { daily_pool_percentage, protocol_fee_val, gpu_penalty_bps, base_buy_rate } => {
+            require!(
+                (MIN_DAILY_POOL_PERCENTAGE..=MAX_DAILY_POOL_PERCENTAGE).contains(daily_pool_percentage),
+                ErrorCode::ConfigOutOfBounds
+            );
+            require!(*protocol_fee_val <= MAX_PROTOCOL_FEE_VAL, ErrorCode::ConfigOutOfBounds);
+            require!(*gpu_penalty_bps <= MAX_GPU_PENALTY_BPS, ErrorCode::ConfigOutOfBounds);
+            require!((MIN_BASE_BUY_RATE..=MAX_BASE_BUY_RATE).contains(base_buy_rate), ErrorCode::ConfigOutOfBounds);
+        }
+    }
+    
+    Ok(())
+}
+
+/// Delay between proposing and executing an admin action (legacy deployments get the default)
+fn timelock_delay(global_state: &GlobalState) -> u32 {
+    match global_state.timelock_delay_secs {
+        0 => DEFAULT_TIMELOCK_DELAY_SECS,
+        delay => delay.clamp(MIN_TIMELOCK_DELAY_SECS, MAX_TIMELOCK_DELAY_SECS),
+    }
+}
+
+// Overwrite the cached USD prices from the oracle accounts (no-op in manual mode)
+fn refresh_prices(
+    global_state: &mut GlobalState,
+    sol_price_feed: Option<&AccountInfo>,
+    gpu_price_feed: Option<&AccountInfo>,
+    currency: PaymentCurrency,
+    gpu_vault_balance: u64,
+    clock: &Clock,
+) -> Result<()> {
+    let current_time = clock.unix_timestamp;
+    
+    // Manual prices are only as fresh as the last update_prices call (0 = no limit)
+    if global_state.price_source == PriceSource::Manual {
+        require!(
+            global_state.manual_price_max_age_secs == 0
+                || current_time.saturating_sub(global_state.price_updated_at) <= global_state.manual_price_max_age_secs as i64,
+            ErrorCode::StalePrice
+        );
+        return Ok(());
+    }
+    
+    // Every quote prices against SOL. The GPU feed is read for GPU deposits and
+    // whenever GPU counts towards TVL, so only an empty GPU vault lets SOL buys
+    // skip it.
+    let sol_price_feed = sol_price_feed.ok_or(ErrorCode::MissingPriceFeed)?;
+    global_state.sol_usd_price = read_pyth_price(
+        sol_price_feed,
+        global_state.max_price_age_secs,
+        global_state.max_confidence_bps,
+        current_time,
+    )?;
+    
+    if currency == PaymentCurrency::Gpu || gpu_vault_balance > 0 {
+        let gpu_price_feed = gpu_price_feed.ok_or(ErrorCode::MissingPriceFeed)?;
+        global_state.gpu_usd_price = read_pyth_price(
+            gpu_price_feed,
+            global_state.max_price_age_secs,
+            global_state.max_confidence_bps,
+            current_time,
+        )?;
+        
+        // Only a read of both feeds counts as a price update
+        global_state.price_updated_slot = clock.slot;
+        global_state.price_updated_at = current_time;
+    }
+    
+    Ok(())
+}
+
+// Store manual prices and stamp them with the current slot and time. An override
+// also starts a new move window from the forced prices.
+fn set_prices(global_state: &mut GlobalState, sol_usd_price: u64, gpu_usd_price: u64, large_move_override: bool, clock: &Clock) {
+    global_state.sol_usd_price = sol_usd_price;
+    global_state.gpu_usd_price = gpu_usd_price;
+    global_state.price_updated_slot = clock.slot;
+    global_state.price_updated_at = clock.unix_timestamp;
+    if large_move_override {
+        global_state.price_window_start = clock.unix_timestamp;
+        global_state.window_sol_usd_price = sol_usd_price;
+        global_state.window_gpu_usd_price = gpu_usd_price;
+    }
+    
+    emit!(PricesUpdated {
+        sol_usd_price,
+        gpu_usd_price,
+        large_move_override,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    });
+}
+
+// Check a routine update against the prices at the start of the move window,
+// opening a new window from the current prices once the old one has run out
+fn check_manual_prices(global_state: &mut GlobalState, sol_usd_price: u64, gpu_usd_price: u64, current_time: i64) -> Result<()> {
+    if current_time >= global_state.price_window_start.saturating_add(PRICE_MOVE_WINDOW_SECS) {
+        global_state.price_window_start = current_time;
+        global_state.window_sol_usd_price = global_state.sol_usd_price;
+        global_state.window_gpu_usd_price = global_state.gpu_usd_price;
+    }
+    
+    check_price_move(global_state.window_sol_usd_price, sol_usd_price, global_state.max_price_move_bps)?;
+    check_price_move(global_state.window_gpu_usd_price, gpu_usd_price, global_state.max_price_move_bps)
+}
+
+// Reject a manual price update that moves more than `max_move_bps` from the
+// reference price (first price and a 0 cap are always accepted)
+fn check_price_move(old_price: u64, new_price: u64, max_move_bps: u16) -> Result<()> {
+    if old_price == 0 || max_move_bps == 0 {
+        return Ok(());
+    }
+    
+    let moved = (old_price.abs_diff(new_price) as u128)
+        .checked_mul(10_000)
+        .ok_or(ErrorCode::Overflow)?;
+    let allowed = (old_price as u128)
+        .checked_mul(max_move_bps as u128)
+        .ok_or(ErrorCode::Overflow)?;
+    require!(moved <= allowed, ErrorCode::PriceMoveTooLarge);
+    
+    Ok(())
+}
+
+// Read a Pyth `PriceUpdateV2` account as a USD price with 8 decimals, rejecting
+// stale, partially verified or low-confidence prices
+fn read_pyth_price(
+    feed: &AccountInfo,
+    max_price_age_secs: u32,
+    max_confidence_bps: u16,
+    current_time: i64,
+) -> Result<u64> {
+    require_keys_eq!(*feed.owner, PYTH_RECEIVER_PROGRAM_ID, ErrorCode::InvalidPriceFeed);
+    
+    let data = feed.try_borrow_data()?;
+    require!(data.len() > 8 && data[..8] == PYTH_PRICE_UPDATE_DISCRIMINATOR, ErrorCode::InvalidPriceFeed);
+    let update = PythPriceUpdate::deserialize(&mut &data[8..]).map_err(|_| ErrorCode::InvalidPriceFeed)?;
+    require!(update.verification_level == PythVerificationLevel::Full, ErrorCode::InvalidPriceFeed);
+    
+    let message = update.price_message;
+    require!(message.price > 0, ErrorCode::InvalidPriceFeed);
+    require!(
+        current_time.saturating_sub(message.publish_time) <= max_price_age_secs as i64,
+        ErrorCode::StalePrice
+    );
+    
+    // Confidence interval as a share of the price
+    let price = message.price as u128;
+    require!(
+        (message.conf as u128).checked_mul(10_000).ok_or(ErrorCode::Overflow)?
+            <= price.checked_mul(max_confidence_bps as u128).ok_or(ErrorCode::Overflow)?,
+        ErrorCode::PriceTooUncertain
+    );
+    
+    // Rescale from 10^exponent to 10^-8
+    let scale = message.exponent.checked_add(8).ok_or(ErrorCode::Overflow)?;
+    let price_8_decimals = if scale >= 0 {
+        price.checked_mul(10u128.checked_pow(scale as u32).ok_or(ErrorCode::Overflow)?)
+    } else {
+        price.checked_div(10u128.checked_pow(scale.unsigned_abs()).ok_or(ErrorCode::Overflow)?)
+    }
+    .ok_or(ErrorCode::Overflow)?;
+    require!(price_8_decimals > 0, ErrorCode::InvalidPriceFeed);
+    
+    u64::try_from(price_8_decimals).map_err(|_| ErrorCode::Overflow.into())
+}
+
+// Price a deposit in MH/s: the protocol fee comes off the deposit once, and the
+// rest is priced by its USD value (with the GPU penalty) against the current TVL.
+// Shared by the buys and `get_mhs_quote`.
+fn quote_mhs(
+    global_state: &GlobalState,
+    currency: PaymentCurrency,
+    amount: u64,
+    sol_vault_balance: u64,
+    gpu_vault_balance: u64,
+) -> Result<MhsQuote> {
+    require!(global_state.sol_usd_price > 0, ErrorCode::PriceNotSet);
+    
+    // The fee slice is routed by the fee split or left in the vault, never priced
+    let (fee_amount, net_amount) = split_protocol_fee(amount, global_state.protocol_fee_val)?;
+    
+    // Convert the net deposit to USD; GPU deposits are priced at (10000 - gpu_penalty_bps) / 10000
+    // of their value, so GPU buyers pay more per MH/s
+    let (usd_value, penalty_usd) = match currency {
+        PaymentCurrency::Sol => (sol_to_usd(net_amount, global_state.sol_usd_price)?, 0),
+        PaymentCurrency::Gpu => {
+            require!(global_state.gpu_usd_price > 0, ErrorCode::PriceNotSet);
+            let usd_value = gpu_to_usd(net_amount, global_state.gpu_usd_price)?;
+            let penalty_usd = usd_value
+                .checked_mul(global_state.gpu_penalty_bps as u128)
+                .ok_or(ErrorCode::Overflow)?
+                .checked_div(10_000)
+                .ok_or(ErrorCode::DivisionByZero)?;
+            (usd_value, penalty_usd)
+        }
+    };
+    let priced_usd = usd_value.checked_sub(penalty_usd).ok_or(ErrorCode::Overflow)?;
+    
+    // Calculate total TVL in USD (SOL + GPU); GPU in the vault must have a price,
+    // or leaving it out would under-charge the buyer
+    require!(gpu_vault_balance == 0 || global_state.gpu_usd_price > 0, ErrorCode::PriceNotSet);
+    let total_tvl_usd = sol_to_usd(sol_vault_balance, global_state.sol_usd_price)?
+        .checked_add(gpu_to_usd(gpu_vault_balance, global_state.gpu_usd_price)?)
+        .ok_or(ErrorCode::Overflow)?;
+    
+    let mhs_after_fee = calculate_mhs_for_usd(priced_usd, global_state, total_tvl_usd)?;
+    
+    Ok(MhsQuote {
+        usd_value: u64::try_from(usd_value).map_err(|_| ErrorCode::Overflow)?,
+        penalty_usd: u64::try_from(penalty_usd).map_err(|_| ErrorCode::Overflow)?,
+        fee_amount,
+        mhs_after_fee,
+    })
+}
+
+// SOL has 9 decimals, price has 8 - result is USD with 8 decimals
+fn sol_to_usd(lamports: u64, sol_usd_price: u64) -> Result<u128> {
+    (lamports as u128)
+        .checked_mul(sol_usd_price as u128)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(1_000_000_000)
+        .ok_or(ErrorCode::DivisionByZero.into())
+}
+
+// GPU has GPU_TOKEN_DECIMALS, price has 8 - result is USD with 8 decimals
+fn gpu_to_usd(amount: u64, gpu_usd_price: u64) -> Result<u128> {
+    (amount as u128)
+        .checked_mul(gpu_usd_price as u128)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(10u128.pow(GPU_TOKEN_DECIMALS as u32))
+        .ok_or(ErrorCode::DivisionByZero.into())
+}
+
+// Split a claim into (protocol fee, user payout)
+fn split_protocol_fee(amount: u64, protocol_fee_val: u8) -> Result<(u64, u64)> {
+    let fee = amount.checked_mul(protocol_fee_val as u64)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(100)
+        .ok_or(ErrorCode::DivisionByZero)?;
+    let payout = amount.checked_sub(fee).ok_or(ErrorCode::Overflow)?;
+    
+    Ok((fee, payout))
+}
+
+// `mhs_amount`'s pro-rata share of `tvl`
+fn share_of(tvl: u64, mhs_amount: u64, total_mining_power: u64) -> Result<u64> {
+    require!(total_mining_power > 0, ErrorCode::DivisionByZero);
+    let share = (tvl as u128)
+        .checked_mul(mhs_amount as u128)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(total_mining_power as u128)
+        .ok_or(ErrorCode::DivisionByZero)?;
+    
+    u64::try_from(share).map_err(|_| ErrorCode::Overflow.into())
+}
+
+// Price a sell of `mhs_amount` MH/s: their pro-rata share of the net TVL (less
+// reserved rewards, the protocol seed and the vault's rent-exempt minimum), less
+// the exit fee
+fn quote_sell(
+    global_state: &GlobalState,
+    mhs_amount: u64,
+    sol_vault_balance: u64,
+    gpu_vault_balance: u64,
+    vault_rent_minimum: u64,
+) -> Result<SellQuote> {
+    let net_sol_tvl = sol_vault_balance
+        .saturating_sub(global_state.total_unclaimed_sol)
+        .saturating_sub(global_state.protocol_seed_sol)
+        .saturating_sub(vault_rent_minimum);
+    let net_gpu_tvl = gpu_vault_balance
+        .saturating_sub(global_state.total_unclaimed_gpu)
+        .saturating_sub(global_state.protocol_seed_gpu);
+    
+    let sol_gross = share_of(net_sol_tvl, mhs_amount, global_state.total_mining_power)?;
+    let gpu_gross = share_of(net_gpu_tvl, mhs_amount, global_state.total_mining_power)?;
+    
+    let exit_fee_bps = exit_fee_bps(global_state);
+    let sol_fee = apply_bps(sol_gross, exit_fee_bps)?;
+    let gpu_fee = apply_bps(gpu_gross, exit_fee_bps)?;
+    
+    Ok(SellQuote {
+        sol_payout: sol_gross.checked_sub(sol_fee).ok_or(ErrorCode::Overflow)?,
+        sol_fee,
+        gpu_payout: gpu_gross.checked_sub(gpu_fee).ok_or(ErrorCode::Overflow)?,
+        gpu_fee,
+    })
+}
+
+// Exit fee on sells (legacy deployments get the default)
+fn exit_fee_bps(global_state: &GlobalState) -> u16 {
+    match global_state.exit_fee_bps {
+        0 => DEFAULT_EXIT_FEE_BPS,
+        bps => bps,
+    }
+}
+
+// Take `sets` × fee_split_count recipient accounts off the front of the remaining accounts
+fn split_fee_recipients<'a, 'info>(
+    global_state: &GlobalState,
+    remaining_accounts: &'a [AccountInfo<'info>],
+    sets: usize,
+) -> Result<(&'a [AccountInfo<'info>], &'a [AccountInfo<'info>])> {
+    let needed = global_state.fee_split_count as usize * sets;
+    require!(remaining_accounts.len() >= needed, ErrorCode::MissingFeeRecipients);
+    
+    Ok(remaining_accounts.split_at(needed))
+}
+
+// Buy fee leaving the vault: zero until a fee split is configured, and net of
+// referral rewards already funded from it
+fn routed_buy_fee(global_state: &GlobalState, amount: u64, referral_reward: u64) -> Result<u64> {
+    if global_state.fee_split_count == 0 {
+        return Ok(0);
+    }
+    let (fee, _) = split_protocol_fee(amount, global_state.protocol_fee_val)?;
+    
+    Ok(fee.saturating_sub(referral_reward))
+}
+
+// Each recipient's cut of `amount`; the last one takes the rounding dust
+fn fee_split_amounts(global_state: &GlobalState, amount: u64) -> Result<Vec<u64>> {
+    let shares = &global_state.fee_split[..global_state.fee_split_count as usize];
+    let mut amounts = Vec::with_capacity(shares.len());
+    let mut remaining = amount;
+    
+    for (i, share) in shares.iter().enumerate() {
+        let cut = if i + 1 == shares.len() { remaining } else { apply_bps(amount, share.bps)? };
+        remaining = remaining.checked_sub(cut).ok_or(ErrorCode::Overflow)?;
+        amounts.push(cut);
+    }
+    
+    Ok(amounts)
+}
+
+// Pay `amount` lamports from the SOL vault across the fee split; `recipients`
+// are the recipient wallets in table order. A cut that would leave its wallet
+// below rent exemption (e.g. a closed wallet) goes to the treasury instead, or
+// stays in the vault while there is no treasury yet.
+#[allow(clippy::too_many_arguments)]
+fn distribute_sol_fee<'info>(
+    global_state: &mut GlobalState,
+    amount: u64,
+    vault: &AccountInfo<'info>,
+    vault_bump: u8,
+    recipients: &[AccountInfo<'info>],
+    treasury: Option<&AccountInfo<'info>>,
+    system_program: &AccountInfo<'info>,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    let mut amounts = fee_split_amounts(global_state, amount)?;
+    let signer_seeds: &[&[&[u8]]] = &[&[b"vault", &[vault_bump]]];
+    let rent = Rent::get()?;
+    let mut treasury_amount = 0u64;
+    
+    for ((share, recipient), cut) in global_state.fee_split.iter().zip(recipients).zip(amounts.iter_mut()) {
+        require_keys_eq!(recipient.key(), share.recipient, ErrorCode::InvalidFeeRecipient);
+        if *cut == 0 {
+            continue;
+        }
+        let balance_after = recipient.lamports().checked_add(*cut).ok_or(ErrorCode::Overflow)?;
+        if !rent.is_exempt(balance_after, recipient.data_len()) {
+            treasury_amount = treasury_amount.checked_add(*cut).ok_or(ErrorCode::Overflow)?;
+            *cut = 0;
+            continue;
+        }
+        anchor_lang::system_program::transfer(
+            CpiContext::new_with_signer(
+                system_program.clone(),
+                anchor_lang::system_program::Transfer {
+                    from: vault.clone(),
+                    to: recipient.clone(),
+                },
+                signer_seeds,
+            ),
+            *cut,
+        )?;
+    }
+    
+    let mut kept_in_vault = 0u64;
+    if treasury_amount > 0 {
+        match treasury {
+            Some(treasury) => anchor_lang::system_program::transfer(
+                CpiContext::new_with_signer(
+                    system_program.clone(),
+                    anchor_lang::system_program::Transfer {
+                        from: vault.clone(),
+                        to: treasury.clone(),
+                    },
+                    signer_seeds,
+                ),
+                treasury_amount,
+            )?,
+            // Before the treasury exists the unpaid cuts simply stay in the vault
+            None => kept_in_vault = std::mem::take(&mut treasury_amount),
+        }
+    }
+    
+    global_state.total_sol_fees = global_state.total_sol_fees
+        .checked_add(amount - kept_in_vault)
+        .ok_or(ErrorCode::Overflow)?;
+    
+    emit!(FeesDistributed {
+        currency: PaymentCurrency::Sol,
+        amount,
+        recipients: recipients.iter().map(|r| r.key()).collect(),
+        amounts,
+        treasury_amount,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    
+    Ok(())
+}
+
+// Pay `amount` GPU from the GPU vault across the fee split; `recipients` are
+// the recipients' gpu_token_mint ATAs in table order. A cut whose ATA does not
+// exist or cannot receive goes to the treasury GPU account instead.
+#[allow(clippy::too_many_arguments)]
+fn distribute_gpu_fee<'info>(
+    global_state: &mut GlobalState,
+    amount: u64,
+    gpu_vault: &AccountInfo<'info>,
+    gpu_vault_authority: &AccountInfo<'info>,
+    gpu_vault_bump: u8,
+    recipients: &[AccountInfo<'info>],
+    treasury_gpu_account: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    let mut amounts = fee_split_amounts(global_state, amount)?;
+    let signer_seeds: &[&[&[u8]]] = &[&[b"gpu_vault", &[gpu_vault_bump]]];
+    let mut treasury_amount = 0u64;
+    
+    for ((share, recipient), cut) in global_state.fee_split.iter().zip(recipients).zip(amounts.iter_mut()) {
+        let expected_account = get_associated_token_address(&share.recipient, &global_state.gpu_token_mint);
+        require_keys_eq!(recipient.key(), expected_account, ErrorCode::InvalidFeeRecipient);
+        if *cut == 0 {
+            continue;
+        }
+        if !can_receive_gpu(recipient, &global_state.gpu_token_mint) {
+            treasury_amount = treasury_amount.checked_add(*cut).ok_or(ErrorCode::Overflow)?;
+            *cut = 0;
+            continue;
+        }
+        token::transfer(
+            CpiContext::new_with_signer(
+                token_program.clone(),
+                SplTransfer {
+                    from: gpu_vault.clone(),
+                    to: recipient.clone(),
+                    authority: gpu_vault_authority.clone(),
+                },
+                signer_seeds,
+            ),
+            *cut,
+        )?;
+    }
+    
+    if treasury_amount > 0 {
+        token::transfer(
+            CpiContext::new_with_signer(
+                token_program.clone(),
+                SplTransfer {
+                    from: gpu_vault.clone(),
+                    to: treasury_gpu_account.clone(),
+                    authority: gpu_vault_authority.clone(),
+                },
+                signer_seeds,
+            ),
+            treasury_amount,
+        )?;
+    }
+    
+    global_state.total_gpu_fees = global_state.total_gpu_fees
+        .checked_add(amount)
+        .ok_or(ErrorCode::Overflow)?;
+    
+    emit!(FeesDistributed {
+        currency: PaymentCurrency::Gpu,
+        amount,
+        recipients: recipients.iter().map(|r| r.key()).collect(),
+        amounts,
+        treasury_amount,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    
+    Ok(())
+}
+
+// Whether `account` is an initialized, unfrozen SPL token account of `mint`
+fn can_receive_gpu(account: &AccountInfo, mint: &Pubkey) -> bool {
+    if account.owner != &token::ID {
+        return false;
+    }
+    let Ok(data) = account.try_borrow_data() else {
+        return false;
+    };
+    match TokenAccount::try_deserialize(&mut &data[..]) {
+        Ok(token_account) => token_account.mint == *mint && !token_account.is_frozen(),
+        Err(_) => false,
+    }
+}
+
+// Run pool release and settlement on copies of the accounts, for the views
+fn simulate_settlement(accounts: &UserView) -> Result<(GlobalState, UserState)> {
+    let clock = Clock::get()?;
+    let mut global_state = (*accounts.global_state).clone();
+    let mut user_state = (*accounts.user_state).clone();
+    
+    let gpu_vault_balance = optional_gpu_vault_balance(&global_state, accounts.gpu_vault.as_deref())?;
+    update_pools(
+        &mut global_state,
+        accounts.sol_vault.to_account_info().lamports(),
+        gpu_vault_balance,
+        clock.unix_timestamp,
+    )?;
+    settle_user(&mut user_state, &global_state, clock.unix_timestamp)?;
+    
+    Ok((global_state, user_state))
+}
+
+// Calculate MH/s for a USD amount, blending genesis pricing into the TVL curve.
+// While the network is below the genesis thresholds buys are priced off
+// `base_buy_rate`; the curve's weight grows with progress towards the
+// thresholds so the rate hands over without a jump.
+fn calculate_mhs_for_usd(usd_amount: u128, global_state: &GlobalState, tvl_usd: u128) -> Result<u64> {
+    let total_mhs = global_state.total_mining_power;
+    
+    let genesis_mhs = calculate_genesis_mhs(usd_amount, global_state.base_buy_rate, global_state.sol_usd_price)?;
+    
+    // Curve is undefined until there is hashrate and TVL to price against
+    if tvl_usd == 0 || total_mhs == 0 {
+        return Ok(genesis_mhs);
+    }
+    
+    let curve_mhs = calculate_curve_mhs(usd_amount, total_mhs, tvl_usd)?;
+    
+    // Progress towards the end of genesis, in bps. A zero threshold is ignored and
+    // with both at zero genesis is off, so the curve takes the full weight.
+    let mhs_progress_bps = progress_bps(total_mhs as u128, global_state.genesis_mhs_threshold as u128)?;
+    let tvl_progress_bps = progress_bps(tvl_usd, global_state.genesis_tvl_threshold_usd as u128)?;
+    let curve_weight_bps = match (mhs_progress_bps, tvl_progress_bps) {
+        (None, None) => 10_000,
+        (mhs, tvl) => mhs.unwrap_or(0).max(tvl.unwrap_or(0)),
+    };
+    
+    let blended = (genesis_mhs as u128)
+        .checked_mul(10_000 - curve_weight_bps)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_add((curve_mhs as u128).checked_mul(curve_weight_bps).ok_or(ErrorCode::Overflow)?)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(10_000)
+        .ok_or(ErrorCode::DivisionByZero)?;
+    
+    u64::try_from(blended).map_err(|_| ErrorCode::Overflow.into())
+}
+
+// GENESIS PRICING: flat `base_buy_rate` MH/s per SOL worth of USD
+fn calculate_genesis_mhs(usd_amount: u128, base_buy_rate: u64, sol_usd_price: u64) -> Result<u64> {
+    // MH/s = (USD / SOL price) × base_buy_rate
+    let mhs = usd_amount
+        .checked_mul(base_buy_rate as u128)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(sol_usd_price as u128)
+        .ok_or(ErrorCode::DivisionByZero)?;
+    
+    u64::try_from(mhs).map_err(|_| ErrorCode::Overflow.into())
+}
+
+// How far `value` has come towards `threshold`, capped at 10,000 bps (None for a 0 threshold)
+fn progress_bps(value: u128, threshold: u128) -> Result<Option<u128>> {
+    if threshold == 0 {
+        return Ok(None);
+    }
+    
+    let bps = value
+        .checked_mul(10_000)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(threshold)
+        .ok_or(ErrorCode::DivisionByZero)?;
+    
+    Ok(Some(bps.min(10_000)))
+}
+
+// NEW PRICING: 1% of network hashrate = 2% of total TVL (in USD)
+// Calculate MH/s based on TVL percentage model
+fn calculate_curve_mhs(usd_amount: u128, total_mhs: u64, tvl_usd: u128) -> Result<u64> {
+    // 1% of hashrate costs 2% of TVL
+    // MH/s = (USD / TVL) × total_MH/s × 0.5
+    // Rearranged: MH/s = (USD × total_MH/s) / (TVL × 2)
+    
+    let numerator = usd_amount.checked_mul(total_mhs as u128).ok_or(ErrorCode::Overflow)?;
+    let denominator = tvl_usd.checked_mul(2).ok_or(ErrorCode::Overflow)?;
+    let mhs = numerator.checked_div(denominator).ok_or(ErrorCode::DivisionByZero)?;
+    
+    u64::try_from(mhs).map_err(|_| ErrorCode::Overflow.into())
+}
+
+fn calculate_earnings(
+    global_state: &GlobalState,
+    last_update: i64,
+    current_time: i64,
+    vault_balance: u64,
+) -> Result<u64> {
+    let seconds_in_day = 86_400u128;
+    
+    // Seconds × daily rate over the period, whichever emission epochs it spans
+    let rate_seconds = emission_rate_seconds(global_state, last_update, current_time)?;
+    
+    // Pool rewards pro-rated by time, never more than the vault holds
+    let earnings = (vault_balance as u128)
+        .checked_mul(rate_seconds)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(EMISSION_RATE_PRECISION * seconds_in_day)
+        .ok_or(ErrorCode::DivisionByZero)?
+        .min(vault_balance as u128);
+    
+    u64::try_from(earnings).map_err(|_| ErrorCode::Overflow.into())
+}
+
+/// Daily pool rate (share of the vault, scaled by EMISSION_RATE_PRECISION) times
+/// seconds, summed from `from` to `to`
+fn emission_rate_seconds(global_state: &GlobalState, from: i64, to: i64) -> Result<u128> {
+    let base_rate = global_state.daily_pool_percentage as u128 * EMISSION_RATE_PRECISION / 100;
+    if to <= from {
+        return Ok(0);
+    }
+    if global_state.emission_epoch_secs == 0 {
+        return base_rate.checked_mul((to - from) as u128).ok_or(ErrorCode::Overflow.into());
+    }
+    
+    // Before the schedule starts the pool runs at the base rate
+    let start = global_state.emission_start;
+    let before_start = base_rate
+        .checked_mul(to.min(start).saturating_sub(from.min(start)) as u128)
+        .ok_or(ErrorCode::Overflow)?;
+    let scheduled = emission_integral(global_state, base_rate, to)?
+        .saturating_sub(emission_integral(global_state, base_rate, from)?);
+    
+    before_start.checked_add(scheduled).ok_or(ErrorCode::Overflow.into())
+}
+
+// Rate-seconds released by the schedule between `emission_start` and `time`:
+// base × (len × (1 − d^k) / (1 − d) + d^k × into) after k whole epochs
+fn emission_integral(global_state: &GlobalState, base_rate: u128, time: i64) -> Result<u128> {
+    if time <= global_state.emission_start {
+        return Ok(0);
+    }
+    
+    let epoch_secs = global_state.emission_epoch_secs as u128;
+    let elapsed = (time - global_state.emission_start) as u128;
+    let epochs = elapsed / epoch_secs;
+    let decay = DECAY_INDEX_ONE * global_state.emission_decay_bps as u128 / 10_000;
+    let kept = pow_fixed(decay, epochs as u64)?;
+    
+    // Whole epochs form a geometric series, or just count up with no decay
+    let whole_epochs = if decay == DECAY_INDEX_ONE {
+        epochs.checked_mul(DECAY_INDEX_ONE).ok_or(ErrorCode::Overflow)?
+    } else {
+        (DECAY_INDEX_ONE - kept) * DECAY_INDEX_ONE / (DECAY_INDEX_ONE - decay)
+    };
+    let weighted_secs = whole_epochs
+        .checked_mul(epoch_secs)
+        .and_then(|secs| secs.checked_add(kept * (elapsed % epoch_secs)))
+        .ok_or(ErrorCode::Overflow)?;
+    
+    Ok(base_rate.checked_mul(weighted_secs).ok_or(ErrorCode::Overflow)? / DECAY_INDEX_ONE)
+}
+
+/// Release pool rewards since `last_reward_update` into the per-MH/s indices.
+/// Released rewards are reserved in `total_unclaimed_*` straight away, so they
+/// stop counting towards mineable TVL even before each miner settles.
+fn update_pools(
+    global_state: &mut GlobalState,
+    sol_vault_balance: u64,
+    gpu_vault_balance: u64,
+    current_time: i64,
+) -> Result<()> {
+    apply_decay(global_state, current_time)?;
+    
+    // Nothing accrues before the first update or while nobody is mining
+    let total_weight = reward_weight(global_state)?;
+    if global_state.last_reward_update == 0 || total_weight == 0 {
+        global_state.last_reward_update = current_time;
+        return Ok(());
+    }
+    if current_time <= global_state.last_reward_update {
+        return Ok(());
+    }
+    
+    let mineable_sol_tvl = sol_vault_balance.saturating_sub(global_state.total_unclaimed_sol);
+    let sol_reward = calculate_earnings(
+        global_state,
+        global_state.last_reward_update,
+        current_time,
+        mineable_sol_tvl,
+    )?;
+    let (sol_increment, reserved_sol) = accumulate(0, sol_reward, total_weight)?;
+    
+    let mineable_gpu_tvl = gpu_vault_balance.saturating_sub(global_state.total_unclaimed_gpu);
+    let gpu_reward = calculate_earnings(
+        global_state,
+        global_state.last_reward_update,
+        current_time,
+        mineable_gpu_tvl,
+    )?;
+    let (gpu_increment, reserved_gpu) = accumulate(0, gpu_reward, total_weight)?;
+    
+    // Holders are weighed at full condition, so the index grows by the increment
+    // per worn unit scaled down to the current wear
+    global_state.acc_sol_per_mhs = global_state.acc_sol_per_mhs
+        .checked_add(scale_by_decay(sol_increment, global_state.decay_index)?)
+        .ok_or(ErrorCode::Overflow)?;
+    global_state.acc_gpu_per_mhs = global_state.acc_gpu_per_mhs
+        .checked_add(scale_by_decay(gpu_increment, global_state.decay_index)?)
+        .ok_or(ErrorCode::Overflow)?;
+    global_state.total_unclaimed_sol = global_state.total_unclaimed_sol
+        .checked_add(reserved_sol)
+        .ok_or(ErrorCode::Overflow)?;
+    global_state.total_unclaimed_gpu = global_state.total_unclaimed_gpu
+        .checked_add(reserved_gpu)
+        .ok_or(ErrorCode::Overflow)?;
+    global_state.last_reward_update = current_time;
+    
+    Ok(())
+}
+
+// Spread `reward` over `total_mhs`, returning the new index and the amount
+// actually distributable after rounding the per-MH/s increment down
+fn accumulate(acc_per_mhs: u128, reward: u64, total_mhs: u64) -> Result<(u128, u64)> {
+    let increment = (reward as u128)
+        .checked_mul(ACC_PRECISION)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(total_mhs as u128)
+        .ok_or(ErrorCode::DivisionByZero)?;
+    let distributed = increment
+        .checked_mul(total_mhs as u128)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(ACC_PRECISION)
+        .ok_or(ErrorCode::DivisionByZero)?;
+    
+    Ok((
+        acc_per_mhs.checked_add(increment).ok_or(ErrorCode::Overflow)?,
+        u64::try_from(distributed).map_err(|_| ErrorCode::Overflow)?,
+    ))
+}
+
+/// Advance the global decay index to `current_time` and wear the MH/s totals with it.
+/// Users, locked positions and rigs are worn to the same index when settled; the
+/// totals round up and holders round down, so each total always covers its holders.
+fn apply_decay(global_state: &mut GlobalState, current_time: i64) -> Result<()> {
+    // Deployments from before wear existed start the index here
+    if global_state.decay_index == 0 {
+        global_state.decay_index = DECAY_INDEX_ONE;
+        global_state.last_decay_update = current_time;
+        return Ok(());
+    }
+    if current_time <= global_state.last_decay_update {
+        return Ok(());
+    }
+    
+    let elapsed = (current_time - global_state.last_decay_update) as u64;
+    global_state.last_decay_update = current_time;
+    if global_state.decay_bps_per_day == 0 {
+        return Ok(());
+    }
+    
+    let old_index = global_state.decay_index;
+    let new_index = old_index
+        .checked_mul(decay_factor(global_state.decay_bps_per_day, elapsed)?)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(DECAY_INDEX_ONE)
+        .ok_or(ErrorCode::DivisionByZero)?
+        .max(1);
+    
+    global_state.total_mining_power = wear_total(global_state.total_mining_power, old_index, new_index)?;
+    global_state.total_locked_mhs = wear_total(global_state.total_locked_mhs, old_index, new_index)?;
+    global_state.total_lock_bonus = wear_total(global_state.total_lock_bonus, old_index, new_index)?;
+    global_state.total_rig_mhs = wear_total(global_state.total_rig_mhs, old_index, new_index)?;
+    global_state.decay_index = new_index;
+    
+    Ok(())
+}
+
+// A total worn from `old_index` to `new_index`, rounded up
+fn wear_total(total: u64, old_index: u128, new_index: u128) -> Result<u64> {
+    let worn = (total as u128)
+        .checked_mul(new_index)
+        .ok_or(ErrorCode::Overflow)?
+        .div_ceil(old_index);
+    
+    u64::try_from(worn).map_err(|_| ErrorCode::Overflow.into())
+}
+
+// A holder's `power` worn from `snapshot` to `decay_index`, rounded down
+// (a 0 snapshot is from before wear existed and counts as index 1)
+fn worn_power(power: u64, snapshot: u128, decay_index: u128) -> Result<u64> {
+    let snapshot = if snapshot == 0 { DECAY_INDEX_ONE } else { snapshot };
+    if snapshot == decay_index {
+        return Ok(power);
+    }
+    let worn = (power as u128)
+        .checked_mul(decay_index)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(snapshot)
+        .ok_or(ErrorCode::DivisionByZero)?;
+    
+    u64::try_from(worn).map_err(|_| ErrorCode::Overflow.into())
+}
+
+// A holder's `power` worn to `snapshot`, scaled back to decay index 1, the unit
+// acc_*_per_mhs is kept in. Constant between settlements however far the index falls.
+fn full_condition(power: u64, snapshot: u128) -> Result<u128> {
+    // Holders never worn (or from before wear existed) sit at index 1
+    if snapshot == 0 {
+        return Ok(power as u128);
+    }
+    
+    (power as u128)
+        .checked_mul(DECAY_INDEX_ONE)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(snapshot)
+        .ok_or(ErrorCode::DivisionByZero.into())
+}
+
+// Share of power left after `elapsed` seconds at `decay_bps_per_day`, scaled by
+// DECAY_INDEX_ONE. Whole days compound, the part day wears linearly.
+fn decay_factor(decay_bps_per_day: u16, elapsed: u64) -> Result<u128> {
+    let day = SECONDS_PER_DAY as u64;
+    let daily_factor = DECAY_INDEX_ONE - DECAY_INDEX_ONE * decay_bps_per_day as u128 / 10_000;
+    
+    let factor = pow_fixed(daily_factor, elapsed / day)?;
+    let part_day = DECAY_INDEX_ONE
+        - DECAY_INDEX_ONE * decay_bps_per_day as u128 * (elapsed % day) as u128 / (10_000 * day as u128);
+    
+    Ok(factor.checked_mul(part_day).ok_or(ErrorCode::Overflow)? / DECAY_INDEX_ONE)
+}
+
+// `base` (scaled by DECAY_INDEX_ONE) to the power `exp`, by squaring
+fn pow_fixed(mut base: u128, mut exp: u64) -> Result<u128> {
+    let mut result = DECAY_INDEX_ONE;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = result.checked_mul(base).ok_or(ErrorCode::Overflow)? / DECAY_INDEX_ONE;
+        }
+        exp >>= 1;
+        if exp > 0 {
+            base = base.checked_mul(base).ok_or(ErrorCode::Overflow)? / DECAY_INDEX_ONE;
+        }
+    }
+    
+    Ok(result)
+}
+
+// Reward index increment for full-condition MH/s at the given wear
+fn scale_by_decay(increment: u128, decay_index: u128) -> Result<u128> {
+    increment
+        .checked_mul(decay_index)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(DECAY_INDEX_ONE)
+        .ok_or(ErrorCode::DivisionByZero.into())
+}
+
+/// Hash needed per compounded MH/s (legacy deployments use the fixed HASH_PER_MHS)
+fn difficulty(global_state: &GlobalState) -> u64 {
+    global_state.difficulty.max(HASH_PER_MHS)
+}
+
+/// Retarget the difficulty once the current epoch has ended. Hashrate growing
+/// faster than `target_growth_bps` per epoch, compounded over every epoch that has
+/// passed, raises it in proportion, slower growth lowers it, by at most
+/// MAX_DIFFICULTY_ADJUSTMENT either way and never below HASH_PER_MHS. Hashrate is
+/// compared at full condition, so wear alone doesn't ease it. Must run after `update_pools`.
+fn retarget_difficulty(global_state: &mut GlobalState, current_time: i64) -> Result<()> {
+    let epoch_secs = global_state.difficulty_epoch_secs as i64;
+    if epoch_secs == 0 || current_time < global_state.difficulty_epoch_start.saturating_add(epoch_secs) {
+        return Ok(());
+    }
+    
+    // Epochs nobody compounded in count towards the target too
+    let epochs = (current_time - global_state.difficulty_epoch_start) / epoch_secs;
+    let old_difficulty = difficulty(global_state);
+    let start_mhs = global_state.epoch_start_mining_power;
+    let end_mhs = full_condition_hashrate(global_state)?;
+    
+    // An epoch that starts from nothing has no growth rate to measure
+    let new_difficulty = if start_mhs == 0 {
+        old_difficulty
+    } else {
+        // (1 + g)^n scaled by DECAY_INDEX_ONE. Growth too large to represent can
+        // only push the retarget to its floor.
+        let growth_per_epoch = DECAY_INDEX_ONE + DECAY_INDEX_ONE * global_state.target_growth_bps as u128 / 10_000;
+        let growth = pow_fixed(growth_per_epoch, epochs as u64).unwrap_or(u128::MAX);
+        let target_mhs = (start_mhs as u128).saturating_mul(growth) / DECAY_INDEX_ONE;
+        let retargeted = (old_difficulty as u128)
+            .checked_mul(end_mhs as u128)
+            .ok_or(ErrorCode::Overflow)?
+            .checked_div(target_mhs)
+            .ok_or(ErrorCode::DivisionByZero)?;
+        let max_difficulty = (old_difficulty as u128)
+            .checked_mul(MAX_DIFFICULTY_ADJUSTMENT as u128)
+            .ok_or(ErrorCode::Overflow)?;
+        let min_difficulty = old_difficulty / MAX_DIFFICULTY_ADJUSTMENT;
+        
+        u64::try_from(retargeted.min(max_difficulty))
+            .map_err(|_| ErrorCode::Overflow)?
+            .max(min_difficulty)
+            .max(HASH_PER_MHS)
+    };
+    
+    // Stay on the epoch grid, so a late retarget doesn't stretch the next epoch
+    global_state.difficulty = new_difficulty;
+    global_state.difficulty_epoch_start = global_state.difficulty_epoch_start
+        .checked_add(epochs.checked_mul(epoch_secs).ok_or(ErrorCode::Overflow)?)
+        .ok_or(ErrorCode::Overflow)?;
+    global_state.epoch_start_mining_power = end_mhs;
+    
+    emit!(DifficultyAdjusted {
+        old_difficulty,
+        new_difficulty,
+        epoch_start_mining_power: start_mhs,
+        epoch_end_mining_power: end_mhs,
+        timestamp: current_time,
+    });
+    
+    Ok(())
+}
+
+/// total_mining_power scaled back to decay index 1, the hashrate difficulty tracks
+fn full_condition_hashrate(global_state: &GlobalState) -> Result<u64> {
+    let hashrate = full_condition(global_state.total_mining_power, global_state.decay_index)?;
+    
+    u64::try_from(hashrate).map_err(|_| ErrorCode::Overflow.into())
+}
+
+/// Pool rewards are shared by reward weight: unlocked MH/s counts 1x, locked
+/// MH/s counts at its term multiplier
+fn reward_weight(global_state: &GlobalState) -> Result<u64> {
+    global_state.total_mining_power
+        .checked_add(global_state.total_lock_bonus)
+        .ok_or(ErrorCode::Overflow.into())
+}
+
+/// Bank a locked position's hash and pool rewards into its owner's ledgers.
+/// Must run after `update_pools`. Returns the (hash, SOL, GPU) banked.
+fn settle_position(
+    position: &mut LockedPosition,
+    user_state: &mut UserState,
+    global_state: &GlobalState,
+    current_time: i64,
+) -> Result<(u64, u64, u64)> {
+    let weight = full_condition(position.reward_weight, position.decay_snapshot)?;
+    let sol = pending_reward(weight, global_state.acc_sol_per_mhs, position.sol_reward_debt)?;
+    let gpu = pending_reward(weight, global_state.acc_gpu_per_mhs, position.gpu_reward_debt)?;
+    
+    // Locked MH/s wears and generates hash at 1x, like unlocked MH/s. The locked
+    // power and the lock bonus wear separately, matching total_locked_mhs and
+    // total_lock_bonus.
+    let lock_bonus = position.reward_weight - position.mining_power;
+    position.mining_power = worn_power(position.mining_power, position.decay_snapshot, global_state.decay_index)?;
+    position.reward_weight = position.mining_power
+        .checked_add(worn_power(lock_bonus, position.decay_snapshot, global_state.decay_index)?)
+        .ok_or(ErrorCode::Overflow)?;
+    position.decay_snapshot = global_state.decay_index;
+    
+    let time_passed = current_time.saturating_sub(position.last_hash_update).max(0) as u64;
+    let hash = time_passed.checked_mul(position.mining_power).ok_or(ErrorCode::Overflow)?;
+    position.last_hash_update = current_time;
+    sync_position_debt(position, global_state)?;
+    
+    user_state.unclaimed_hash = user_state.unclaimed_hash
+        .checked_add(hash)
+        .ok_or(ErrorCode::Overflow)?;
+    user_state.unclaimed_sol = user_state.unclaimed_sol
+        .checked_add(sol)
+        .ok_or(ErrorCode::Overflow)?;
+    user_state.unclaimed_gpu = user_state.unclaimed_gpu
+        .checked_add(gpu)
+        .ok_or(ErrorCode::Overflow)?;
+    
+    Ok((hash, sol, gpu))
+}
+
+fn sync_position_debt(position: &mut LockedPosition, global_state: &GlobalState) -> Result<()> {
+    let weight = full_condition(position.reward_weight, position.decay_snapshot)?;
+    position.sol_reward_debt = accrued_reward(weight, global_state.acc_sol_per_mhs)?;
+    position.gpu_reward_debt = accrued_reward(weight, global_state.acc_gpu_per_mhs)?;
+    
+    Ok(())
+}
+
+/// Fill in a freshly created user account
+fn reset_user_state(user_state: &mut UserState, owner: Pubkey, current_time: i64) {
+    user_state.owner = owner;
+    user_state.mining_power = 0;
+    user_state.unclaimed_sol = 0;
+    user_state.unclaimed_gpu = 0;
+    user_state.last_claim = current_time;
+    user_state.referrer = None;
+    user_state.total_sol_claimed = 0;
+    user_state.total_gpu_claimed = 0;
+    user_state.unclaimed_hash = 0;
+    user_state.last_hash_update = current_time;
+    user_state.sol_reward_debt = 0;
+    user_state.gpu_reward_debt = 0;
+    user_state.referral_sol_earned = 0;
+    user_state.referral_gpu_earned = 0;
+    user_state.pending_sell_sol = 0;
+    user_state.pending_sell_gpu = 0;
+    user_state.sell_unlock_at = 0;
+    user_state.next_lock_id = 0;
+    user_state.locked_mining_power = 0;
+    user_state.rated_mining_power = 0;
+    user_state.decay_snapshot = 0;
+}
+
+/// Bank a rig's hash and pool rewards into the rig's own ledgers. Must run after `update_pools`.
+fn settle_rig(rig: &mut RigPosition, global_state: &GlobalState, current_time: i64) -> Result<()> {
+    let weight = full_condition(rig.mining_power, rig.decay_snapshot)?;
+    let pending_sol = pending_reward(weight, global_state.acc_sol_per_mhs, rig.sol_reward_debt)?;
+    let pending_gpu = pending_reward(weight, global_state.acc_gpu_per_mhs, rig.gpu_reward_debt)?;
+    
+    // Hash accrues at the worn power
+    rig.mining_power = worn_power(rig.mining_power, rig.decay_snapshot, global_state.decay_index)?;
+    rig.decay_snapshot = global_state.decay_index;
+    let time_passed = current_time.saturating_sub(rig.last_hash_update).max(0) as u64;
+    let hash = time_passed.checked_mul(rig.mining_power).ok_or(ErrorCode::Overflow)?;
+    rig.unclaimed_hash = rig.unclaimed_hash.checked_add(hash).ok_or(ErrorCode::Overflow)?;
+    rig.last_hash_update = current_time;
+    
+    rig.unclaimed_sol = rig.unclaimed_sol.checked_add(pending_sol).ok_or(ErrorCode::Overflow)?;
+    rig.unclaimed_gpu = rig.unclaimed_gpu.checked_add(pending_gpu).ok_or(ErrorCode::Overflow)?;
+    
+    sync_rig_debt(rig, global_state)
+}
+
+fn sync_rig_debt(rig: &mut RigPosition, global_state: &GlobalState) -> Result<()> {
+    let weight = full_condition(rig.mining_power, rig.decay_snapshot)?;
+    rig.sol_reward_debt = accrued_reward(weight, global_state.acc_sol_per_mhs)?;
+    rig.gpu_reward_debt = accrued_reward(weight, global_state.acc_gpu_per_mhs)?;
+    
+    Ok(())
+}
+
+/// Move a settled rig's SOL/GPU (and hash, if `include_hash`) into a user's ledgers.
+/// Both sides are already reserved in total_unclaimed_*, so only the owner changes.
+fn move_rig_ledgers(rig: &mut RigPosition, user_state: &mut UserState, include_hash: bool) -> Result<()> {
+    user_state.unclaimed_sol = user_state.unclaimed_sol
+        .checked_add(rig.unclaimed_sol)
+        .ok_or(ErrorCode::Overflow)?;
+    user_state.unclaimed_gpu = user_state.unclaimed_gpu
+        .checked_add(rig.unclaimed_gpu)
+        .ok_or(ErrorCode::Overflow)?;
+    rig.unclaimed_sol = 0;
+    rig.unclaimed_gpu = 0;
+    
+    if include_hash {
+        user_state.unclaimed_hash = user_state.unclaimed_hash
+            .checked_add(rig.unclaimed_hash)
+            .ok_or(ErrorCode::Overflow)?;
+        rig.unclaimed_hash = 0;
+    }
+    
+    Ok(())
+}
+
+/// Plain rigs answer to `owner`; NFT-backed rigs to whoever holds the NFT, who
+/// becomes the recorded owner
+fn authorize_rig_holder(
+    rig: &mut RigPosition,
+    holder: Pubkey,
+    holder_nft_account: Option<&TokenAccount>,
+) -> Result<()> {
+    match rig.nft_mint {
+        None => require_keys_eq!(rig.owner, holder, ErrorCode::Unauthorized),
+        Some(nft_mint) => {
+            let nft_account = holder_nft_account.ok_or(ErrorCode::MissingRigNft)?;
+            require!(
+                nft_account.mint == nft_mint && nft_account.owner == holder && nft_account.amount == 1,
+                ErrorCode::Unauthorized
+            );
+            rig.owner = holder;
+        }
+    }
+    
+    Ok(())
+}
+
+/// Bank hash and pool rewards earned since the user's last settlement.
+/// Must run after `update_pools` and before the user's mining power changes.
+/// Returns the (hash, SOL, GPU) banked by this settlement.
+fn settle_user(user_state: &mut UserState, global_state: &GlobalState, current_time: i64) -> Result<(u64, u64, u64)> {
+    let weight = full_condition_power(user_state)?;
+    let pending_sol = pending_reward(weight, global_state.acc_sol_per_mhs, user_state.sol_reward_debt)?;
+    let pending_gpu = pending_reward(weight, global_state.acc_gpu_per_mhs, user_state.gpu_reward_debt)?;
+    
+    // Hash accrues at the worn power
+    wear_user_power(user_state, global_state)?;
+    let settled_hash = accrue_hash(user_state, current_time)?;
+    
+    user_state.unclaimed_sol = user_state.unclaimed_sol
+        .checked_add(pending_sol)
+        .ok_or(ErrorCode::Overflow)?;
+    user_state.unclaimed_gpu = user_state.unclaimed_gpu
+        .checked_add(pending_gpu)
+        .ok_or(ErrorCode::Overflow)?;
+    
+    sync_reward_debt(user_state, global_state)?;
+    
+    Ok((settled_hash, pending_sol, pending_gpu))
+}
+
+/// Reset reward debt so future rewards accrue from the user's current power
+fn sync_reward_debt(user_state: &mut UserState, global_state: &GlobalState) -> Result<()> {
+    let weight = full_condition_power(user_state)?;
+    user_state.sol_reward_debt = accrued_reward(weight, global_state.acc_sol_per_mhs)?;
+    user_state.gpu_reward_debt = accrued_reward(weight, global_state.acc_gpu_per_mhs)?;
+    
+    Ok(())
+}
+
+/// The user's MH/s scaled back to decay index 1, the unit acc_*_per_mhs is kept in
+fn full_condition_power(user_state: &UserState) -> Result<u128> {
+    full_condition(user_state.mining_power, user_state.decay_snapshot)
+}
+
+/// Wear the user's MH/s down to the current decay index. Must run after `update_pools`.
+fn wear_user_power(user_state: &mut UserState, global_state: &GlobalState) -> Result<()> {
+    // Power from before wear existed counts as rated
+    user_state.rated_mining_power = user_state.rated_mining_power.max(user_state.mining_power);
+    
+    user_state.mining_power = worn_power(user_state.mining_power, user_state.decay_snapshot, global_state.decay_index)?;
+    user_state.decay_snapshot = global_state.decay_index;
+    
+    Ok(())
+}
+
+/// Add `amount` MH/s standing for `rated` MH/s of rated power to the user's wallet.
+/// Fresh MH/s passes its own amount; MH/s coming back from a position, rig or
+/// another wallet passes the rated share it left with, so wear cannot be shed.
+fn add_user_power(user_state: &mut UserState, amount: u64, rated: u64) -> Result<()> {
+    user_state.mining_power = user_state.mining_power
+        .checked_add(amount)
+        .ok_or(ErrorCode::Overflow)?;
+    user_state.rated_mining_power = user_state.rated_mining_power
+        .checked_add(rated)
+        .ok_or(ErrorCode::Overflow)?
+        .max(user_state.mining_power);
+    
+    Ok(())
+}
+
+/// Move `amount` MH/s between settled wallets, burning the transfer fee out of it.
+/// The recipient takes over the wear of what arrives. Returns (fee, received).
+fn transfer_user_power(
+    sender_state: &mut UserState,
+    recipient_state: &mut UserState,
+    global_state: &mut GlobalState,
+    amount: u64,
+) -> Result<(u64, u64)> {
+    let fee = apply_bps(amount, global_state.transfer_fee_bps)?;
+    let received = amount.checked_sub(fee).ok_or(ErrorCode::Overflow)?;
+    
+    let rated = remove_user_power(sender_state, amount)?;
+    add_user_power(recipient_state, received, share_of(rated, received, amount)?)?;
+    global_state.total_mining_power = global_state.total_mining_power
+        .checked_sub(fee)
+        .ok_or(ErrorCode::Overflow)?;
+    
+    Ok((fee, received))
+}
+
+/// Take worn MH/s out of the user's wallet, along with the share of rated power it
+/// stood for. Returns the rated power removed.
+fn remove_user_power(user_state: &mut UserState, amount: u64) -> Result<u64> {
+    let old_rated = user_state.rated_mining_power;
+    let rated_share = (amount as u128)
+        .checked_mul(user_state.rated_mining_power as u128)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(user_state.mining_power.max(1) as u128)
+        .ok_or(ErrorCode::DivisionByZero)?;
+    
+    user_state.mining_power = user_state.mining_power
+        .checked_sub(amount)
+        .ok_or(ErrorCode::Overflow)?;
+    user_state.rated_mining_power = user_state.rated_mining_power
+        .saturating_sub(u64::try_from(rated_share).map_err(|_| ErrorCode::Overflow)?)
+        .max(user_state.mining_power);
+    
+    Ok(old_rated - user_state.rated_mining_power)
+}
+
+fn accrued_reward(weight: u128, acc_per_mhs: u128) -> Result<u128> {
+    weight
+        .checked_mul(acc_per_mhs)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(ACC_PRECISION)
+        .ok_or(ErrorCode::DivisionByZero.into())
+}
+
+// Accrued and debt are each floored, so this can run one unit over the exact share
+fn pending_reward(weight: u128, acc_per_mhs: u128, reward_debt: u128) -> Result<u64> {
+    let pending = accrued_reward(weight, acc_per_mhs)?.saturating_sub(reward_debt);
+    
+    u64::try_from(pending).map_err(|_| ErrorCode::Overflow.into())
+}
+
+/// Shared referral path for both buys. Records `referrer` on the buyer's first
+/// referred buy, then walks the referrer chain paying each level its share of
+/// the schedule. Level 1 is `referrer_state`, deeper levels come from `upline`
+/// in order; each must be the user_state PDA of the previous level's referrer.
+/// The walk stops at the schedule depth, a missing account or a repeated owner.
+/// Returns the total MH/s bonus and deposit-currency reward paid.
+fn settle_referral<'info>(
+    global_state: &mut GlobalState,
+    user_state: &mut UserState,
+    referrer: Option<Pubkey>,
+    referrer_state: Option<&mut Account<'info, UserState>>,
+    upline: &'info [AccountInfo<'info>],
+    purchase: ReferralPurchase,
+    current_time: i64,
+) -> Result<(u64, u64)> {
+    if let (None, Some(ref_key)) = (user_state.referrer, referrer) {
+        require!(ref_key != user_state.owner, ErrorCode::SelfReferral);
+        user_state.referrer = Some(ref_key);
+    }
+    
+    // A referrer without a user account simply earns nothing
+    let Some(referrer_state) = referrer_state else {
+        return Ok((0, 0));
+    };
+    let stored_referrer = user_state.referrer.ok_or(ErrorCode::InvalidReferrer)?;
+    check_referrer_account(referrer_state.key(), stored_referrer)?;
+    
+    // Fee-funded rewards can never pay out more than the buy's protocol fee
+    let (mut fee_budget, _) = split_protocol_fee(purchase.amount, global_state.protocol_fee_val)?;
+    
+    let buyer = user_state.owner;
+    let (mut total_bonus, mut total_reward) = pay_referral(
+        global_state,
+        referrer_state,
+        buyer,
+        0,
+        &purchase,
+        &mut fee_budget,
+        current_time,
+    )?;
+    
+    // Owners already seen on this buy; meeting one again means the chain loops
+    let mut visited = vec![buyer, stored_referrer];
+    let mut next_referrer = referrer_state.referrer;
+    
+    for (level, referrer_info) in (1..referral_depth(global_state)).zip(upline.iter()) {
+        let Some(level_referrer) = next_referrer else {
+            break;
+        };
+        if visited.contains(&level_referrer) {
+            break;
+        }
+        check_referrer_account(referrer_info.key(), level_referrer)?;
+        require!(referrer_info.is_writable, ErrorCode::InvalidReferrer);
+        
+        let mut level_state = Account::<UserState>::try_from(referrer_info)?;
+        let (bonus, reward) = pay_referral(
+            global_state,
+            &mut level_state,
+            buyer,
+            level,
+            &purchase,
+            &mut fee_budget,
+            current_time,
+        )?;
+        level_state.exit(&crate::ID)?;
+        
+        total_bonus = total_bonus.checked_add(bonus).ok_or(ErrorCode::Overflow)?;
+        total_reward = total_reward.checked_add(reward).ok_or(ErrorCode::Overflow)?;
+        visited.push(level_referrer);
+        next_referrer = level_state.referrer;
+    }
+    
+    Ok((total_bonus, total_reward))
+}
+
+/// A referrer account must be the user_state PDA of `referrer`
+fn check_referrer_account(account: Pubkey, referrer: Pubkey) -> Result<()> {
+    let (expected_key, _) = Pubkey::find_program_address(&[b"user_state", referrer.as_ref()], &crate::ID);
+    require_keys_eq!(account, expected_key, ErrorCode::InvalidReferrer);
+    
+    Ok(())
+}
+
+/// Credit one referral level with its bps share of the buy, either as MH/s or,
+/// in `Fees` mode, as deposit currency drawn from what is left of `fee_budget`.
+/// Returns (MH/s bonus, deposit-currency reward).
+fn pay_referral(
+    global_state: &mut GlobalState,
+    referrer_state: &mut UserState,
+    buyer: Pubkey,
+    level: usize,
+    purchase: &ReferralPurchase,
+    fee_budget: &mut u64,
+    current_time: i64,
+) -> Result<(u64, u64)> {
+    let bps = referral_level_bps(global_state, level);
+    let (mut bonus, mut reward) = (0, 0);
+    let (mut settled_hash, mut settled_sol, mut settled_gpu) = (0, 0, 0);
+    
+    match global_state.referral_reward_mode {
+        ReferralRewardMode::MiningPower => {
+            bonus = apply_bps(purchase.mhs_bought, bps)?;
+            
+            // The referrer is settled before its power moves
+            (settled_hash, settled_sol, settled_gpu) = settle_user(referrer_state, global_state, current_time)?;
+            add_user_power(referrer_state, bonus, bonus)?;
+            sync_reward_debt(referrer_state, global_state)?;
+            
+            global_state.total_mining_power = global_state.total_mining_power
+                .checked_add(bonus)
+                .ok_or(ErrorCode::Overflow)?;
+        }
+        ReferralRewardMode::Fees => {
+            reward = apply_bps(purchase.amount, bps)?.min(*fee_budget);
+            *fee_budget -= reward;
+            
+            // The deposit stays in the vault, reserved like unclaimed earnings
+            match purchase.currency {
+                PaymentCurrency::Sol => {
+                    referrer_state.referral_sol_earned = referrer_state.referral_sol_earned
+                        .checked_add(reward)
+                        .ok_or(ErrorCode::Overflow)?;
+                    global_state.total_unclaimed_sol = global_state.total_unclaimed_sol
+                        .checked_add(reward)
+                        .ok_or(ErrorCode::Overflow)?;
+                }
+                PaymentCurrency::Gpu => {
+                    referrer_state.referral_gpu_earned = referrer_state.referral_gpu_earned
+                        .checked_add(reward)
+                        .ok_or(ErrorCode::Overflow)?;
+                    global_state.total_unclaimed_gpu = global_state.total_unclaimed_gpu
+                        .checked_add(reward)
+                        .ok_or(ErrorCode::Overflow)?;
+                }
+            }
+        }
+    }
+    
+    emit!(ReferralPaid {
+        buyer,
+        referrer: referrer_state.owner,
+        level: (level + 1) as u8,
+        bps,
+        bonus,
+        currency: purchase.currency,
+        reward,
+        referrer_mining_power: referrer_state.mining_power,
+        settled_hash,
+        settled_sol,
+        settled_gpu,
+        timestamp: current_time,
+    });
+    
+    Ok((bonus, reward))
+}
+
+/// `bps` basis points of `amount`, rounded down
+fn apply_bps(amount: u64, bps: u16) -> Result<u64> {
+    let share = (amount as u128)
+        .checked_mul(bps as u128)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(10_000)
+        .ok_or(ErrorCode::DivisionByZero)?;
+    
+    u64::try_from(share).map_err(|_| ErrorCode::Overflow.into())
+}
+
+/// Levels the referral walk may visit (legacy deployments pay one level)
+fn referral_depth(global_state: &GlobalState) -> usize {
+    (global_state.referral_levels as usize).clamp(1, MAX_REFERRAL_LEVELS)
+}
+
+/// Share of a buy paid to referral `level` (0 = direct referrer), in bps
+fn referral_level_bps(global_state: &GlobalState, level: usize) -> u16 {
+    match (global_state.referral_levels, level) {
+        (0, 0) => DEFAULT_REFERRAL_BPS,
+        (0, _) => 0,
+        (levels, level) if level < levels as usize => global_state.referral_bps[level],
+        _ => 0,
+    }
+}
+
+/// Bank hash generated since `last_hash_update` at the current mining power
+fn accrue_hash(user_state: &mut UserState, current_time: i64) -> Result<u64> {
+    let time_passed = current_time.saturating_sub(user_state.last_hash_update).max(0) as u64;
+    let hash_generated = time_passed.checked_mul(user_state.mining_power).ok_or(ErrorCode::Overflow)?;
+    
+    user_state.unclaimed_hash = user_state.unclaimed_hash
+        .checked_add(hash_generated)
+        .ok_or(ErrorCode::Overflow)?;
+    user_state.last_hash_update = current_time;
+    
+    Ok(hash_generated)
+}
+
+/// Grow a program account to `new_len`, topping up rent from `payer`
+fn grow_account<'info>(
+    account: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    new_len: usize,
+) -> Result<()> {
+    if account.data_len() >= new_len {
+        return Ok(());
+    }
+    
+    let rent_needed = Rent::get()?.minimum_balance(new_len).saturating_sub(account.lamports());
+    if rent_needed > 0 {
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                system_program.clone(),
+                anchor_lang::system_program::Transfer {
+                    from: payer.clone(),
+                    to: account.clone(),
+                },
+            ),
+            rent_needed,
+        )?;
+    }
+    
+    account.resize(new_len)?;
+    
+    Ok(())
+}
+
+// Rest of structs...
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + GlobalState::INIT_SPACE,
+        seeds = [b"global_state"],
+        bump
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    /// CHECK: Vault
+    #[account(mut, seeds = [b"vault"], bump)]
+    pub vault: AccountInfo<'info>,
+    
+    /// CHECK: GPU Vault Authority PDA
+    #[account(seeds = [b"gpu_vault"], bump)]
+    pub gpu_vault_authority: AccountInfo<'info>,
+    
+    /// Optional GPU seed accounts (required when gpu_seed_amount > 0)
+    #[account(mut)]
+    pub gpu_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    #[account(mut)]
+    pub authority_gpu_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    pub token_program: Option<Program<'info, Token>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitUser<'info> {
+    #[account(
+        init,
+        payer = user,
+        space = 8 + UserState::INIT_SPACE,
+        seeds = [b"user_state", user.key().as_ref()],
+        bump
+    )]
+    pub user_state: Account<'info, UserState>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateUserState<'info> {
+    /// CHECK: Deserialized by hand after growing, legacy layouts can't be loaded as `Account`
+    #[account(mut, seeds = [b"user_state", user.key().as_ref()], bump, owner = crate::ID)]
+    pub user_state: UncheckedAccount<'info>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct BuyWithGpu<'info> {
+    #[account(mut, seeds = [b"global_state"], bump)]
+    pub global_state: Account<'info, GlobalState>,
+    
+    #[account(mut, seeds = [b"user_state", buyer.key().as_ref()], bump)]
+    pub user_state: Account<'info, UserState>,
+    
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    
+    /// CHECK: SOL Vault (for TVL calculation)
+    #[account(mut, seeds = [b"vault"], bump)]
+    pub sol_vault: AccountInfo<'info>,
+    
+    /// Canonical GPU vault: the gpu_token_mint ATA of the gpu_vault PDA
+    #[account(
+        mut,
+        associated_token::mint = global_state.gpu_token_mint,
+        associated_token::authority = gpu_vault_authority,
+        associated_token::token_program = token_program,
+    )]
+    pub gpu_vault: InterfaceAccount<'info, TokenAccount>,
+    
+    /// CHECK: GPU Vault Authority PDA
+    #[account(seeds = [b"gpu_vault"], bump)]
+    pub gpu_vault_authority: AccountInfo<'info>,
+    
+    #[account(mut, token::mint = global_state.gpu_token_mint, token::authority = buyer)]
+    pub buyer_gpu_account: InterfaceAccount<'info, TokenAccount>,
+    
+    /// Protocol treasury, owner of the treasury GPU account
+    #[account(seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+    
+    /// Treasury GPU account, receives fee split cuts that cannot be paid out
+    #[account(mut, token::mint = global_state.gpu_token_mint, token::authority = treasury)]
+    pub treasury_gpu_account: InterfaceAccount<'info, TokenAccount>,
+    
+    /// Optional referrer, must be the user_state PDA of user_state.referrer
+    #[account(mut)]
+    pub referrer_state: Option<Account<'info, UserState>>,
+    
+    /// CHECK: SOL/USD price account, required when price_source is Pyth
+    #[account(address = global_state.sol_price_feed @ ErrorCode::InvalidPriceFeed)]
+    pub sol_price_feed: Option<UncheckedAccount<'info>>,
+    
+    /// CHECK: GPU/USD price account, required when price_source is Pyth
+    #[account(address = global_state.gpu_price_feed @ ErrorCode::InvalidPriceFeed)]
+    pub gpu_price_feed: Option<UncheckedAccount<'info>>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct BuyMiningPower<'info> {
+    #[account(mut, seeds = [b"global_state"], bump)]
+    pub global_state: Account<'info, GlobalState>,
+    
+    #[account(mut, seeds = [b"user_state", buyer.key().as_ref()], bump)]
+    pub user_state: Account<'info, UserState>,
+    
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    
+    /// CHECK: Vault
+    #[account(mut, seeds = [b"vault"], bump)]
+    pub vault: AccountInfo<'info>,
+    
+    /// Canonical GPU vault: the gpu_token_mint ATA of the gpu_vault PDA
+    /// (may be omitted until a GPU token is set)
+    #[account(
+        associated_token::mint = global_state.gpu_token_mint,
+        associated_token::authority = gpu_vault_authority,
+    )]
+    pub gpu_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    /// CHECK: GPU Vault Authority PDA
+    #[account(seeds = [b"gpu_vault"], bump)]
+    pub gpu_vault_authority: AccountInfo<'info>,
+    
+    /// Protocol treasury, receives fee split cuts that cannot be paid out
+    /// (may be omitted until a GPU token is set)
+    #[account(mut, seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Option<Account<'info, Treasury>>,
+    
+    /// Optional referrer, must be the user_state PDA of user_state.referrer
+    #[account(mut)]
+    pub referrer_state: Option<Account<'info, UserState>>,
+    
+    /// CHECK: SOL/USD price account, required when price_source is Pyth
+    #[account(address = global_state.sol_price_feed @ ErrorCode::InvalidPriceFeed)]
+    pub sol_price_feed: Option<UncheckedAccount<'info>>,
+    
+    /// CHECK: GPU/USD price account, required when price_source is Pyth
+    #[account(address = global_state.gpu_price_feed @ ErrorCode::InvalidPriceFeed)]
+    pub gpu_price_feed: Option<UncheckedAccount<'info>>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CompoundHash<'info> {
+    #[account(mut, seeds = [b"global_state"], bump)]
+    pub global_state: Account<'info, GlobalState>,
+    
+    #[account(mut, seeds = [b"user_state", user.key().as_ref()], bump)]
+    pub user_state: Account<'info, UserState>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    /// CHECK: SOL Vault (for pool updates)
+    #[account(seeds = [b"vault"], bump)]
+    pub sol_vault: AccountInfo<'info>,
+    
+    /// Canonical GPU vault: the gpu_token_mint ATA of the gpu_vault PDA
+    /// (may be omitted until a GPU token is set)
+    #[account(
+        associated_token::mint = global_state.gpu_token_mint,
+        associated_token::authority = gpu_vault_authority,
+    )]
+    pub gpu_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    /// CHECK: GPU Vault Authority PDA
+    #[account(seeds = [b"gpu_vault"], bump)]
+    pub gpu_vault_authority: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitTreasury<'info> {
+    #[account(seeds = [b"global_state"], bump, constraint = global_state.authority == authority.key())]
+    pub global_state: Account<'info, GlobalState>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Treasury::INIT_SPACE,
+        seeds = [b"treasury"],
+        bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+    
+    #[account(address = global_state.gpu_token_mint @ ErrorCode::InvalidMint)]
+    pub gpu_token_mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(
+        init,
+        payer = authority,
+        associated_token::mint = gpu_token_mint,
+        associated_token::authority = treasury,
+        associated_token::token_program = token_program,
+    )]
+    pub treasury_gpu_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct BurnGpu<'info> {
+    #[account(mut, seeds = [b"global_state"], bump)]
+    pub global_state: Account<'info, GlobalState>,
+    
+    #[account(seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+    
+    #[account(mut, address = global_state.gpu_token_mint @ ErrorCode::InvalidMint)]
+    pub gpu_token_mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(mut, token::mint = gpu_token_mint, token::authority = treasury)]
+    pub treasury_gpu_account: InterfaceAccount<'info, TokenAccount>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeAdminAction<'info> {
+    #[account(mut, seeds = [b"global_state"], bump, constraint = global_state.authority == authority.key())]
+    pub global_state: Account<'info, GlobalState>,
+    
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + Proposal::INIT_SPACE,
+        seeds = [b"proposal", global_state.next_proposal_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub proposal: Account<'info, Proposal>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteProposal<'info> {
+    #[account(mut, seeds = [b"global_state"], bump, constraint = global_state.authority == authority.key())]
+    pub global_state: Account<'info, GlobalState>,
+    
+    #[account(mut, seeds = [b"proposal", proposal.id.to_le_bytes().as_ref()], bump = proposal.bump)]
+    pub proposal: Account<'info, Proposal>,
+    
+    /// CHECK: Vault PDA
+    #[account(mut, seeds = [b"vault"], bump)]
+    pub vault: AccountInfo<'info>,
+    
+    /// Treasury PDA (required by WithdrawBuybackSol)
+    #[account(mut, seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Option<Account<'info, Treasury>>,
+    
+    /// CHECK: The fixed buyback wallet (required by WithdrawBuybackSol)
+    #[account(mut, address = global_state.buyback_wallet @ ErrorCode::InvalidRecipient)]
+    pub buyback_wallet: Option<UncheckedAccount<'info>>,
+    
+    /// Canonical GPU vault, for the pool update before ResetUserPower, SetDecayParams,
+    /// SetEmissionSchedule and UpdateConfig (may be omitted until a GPU token is set)
+    #[account(
+        associated_token::mint = global_state.gpu_token_mint,
+        associated_token::authority = gpu_vault_authority,
+    )]
+    pub gpu_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    /// CHECK: GPU Vault Authority PDA
+    #[account(seeds = [b"gpu_vault"], bump)]
+    pub gpu_vault_authority: AccountInfo<'info>,
+    
+    /// Target of ResetUserPower
+    #[account(mut)]
+    pub user_state: Option<Account<'info, UserState>>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelProposal<'info> {
+    #[account(seeds = [b"global_state"], bump, constraint = global_state.authority == authority.key())]
+    pub global_state: Account<'info, GlobalState>,
+    
+    #[account(mut, seeds = [b"proposal", proposal.id.to_le_bytes().as_ref()], bump = proposal.bump)]
+    pub proposal: Account<'info, Proposal>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimEarnings<'info> {
+    #[account(mut, seeds = [b"global_state"], bump)]
+    pub global_state: Account<'info, GlobalState>,
+    
+    #[account(mut, seeds = [b"user_state", user.key().as_ref()], bump)]
+    pub user_state: Account<'info, UserState>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    /// CHECK: SOL Vault
+    #[account(mut, seeds = [b"vault"], bump)]
+    pub sol_vault: AccountInfo<'info>,
+    
+    /// Canonical GPU vault: the gpu_token_mint ATA of the gpu_vault PDA
+    /// (may be omitted until a GPU token is set)
+    #[account(
+        mut,
+        associated_token::mint = global_state.gpu_token_mint,
+        associated_token::authority = gpu_vault_authority,
+        associated_token::token_program = token_program,
+    )]
+    pub gpu_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    /// CHECK: GPU Vault Authority PDA
+    #[account(seeds = [b"gpu_vault"], bump)]
+    pub gpu_vault_authority: AccountInfo<'info>,
+    
+    /// User's GPU token account, required when GPU earnings are paid
+    #[account(mut, token::mint = global_state.gpu_token_mint, token::authority = user)]
+    pub user_gpu_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    /// Protocol treasury, receives the SOL fee (may be omitted until a GPU token is set)
+    #[account(mut, seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Option<Account<'info, Treasury>>,
+    
+    /// Treasury GPU account, receives the GPU fee for burning; required when GPU earnings are paid
+    #[account(mut, token::mint = global_state.gpu_token_mint, token::authority = treasury)]
+    pub treasury_gpu_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(amount: u64, recipient: Pubkey)]
+pub struct TransferMiningPower<'info> {
+    #[account(mut, seeds = [b"global_state"], bump)]
+    pub global_state: Account<'info, GlobalState>,
+    
+    #[account(mut, seeds = [b"user_state", sender.key().as_ref()], bump)]
+    pub sender_state: Account<'info, UserState>,
+    
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = 8 + UserState::INIT_SPACE,
+        seeds = [b"user_state", recipient.as_ref()],
+        bump,
+        constraint = recipient != sender.key() @ ErrorCode::InvalidRecipient
+    )]
+    pub recipient_state: Account<'info, UserState>,
+    
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    
+    /// CHECK: SOL Vault (for pool updates)
+    #[account(seeds = [b"vault"], bump)]
+    pub sol_vault: AccountInfo<'info>,
+    
+    /// Canonical GPU vault: the gpu_token_mint ATA of the gpu_vault PDA
+    /// (may be omitted until a GPU token is set)
+    #[account(
+        associated_token::mint = global_state.gpu_token_mint,
+        associated_token::authority = gpu_vault_authority,
+    )]
+    pub gpu_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    /// CHECK: GPU Vault Authority PDA
+    #[account(seeds = [b"gpu_vault"], bump)]
+    pub gpu_vault_authority: AccountInfo<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SellMiningPower<'info> {
+    #[account(mut, seeds = [b"global_state"], bump)]
+    pub global_state: Account<'info, GlobalState>,
+    
+    #[account(mut, seeds = [b"user_state", user.key().as_ref()], bump)]
+    pub user_state: Account<'info, UserState>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    /// CHECK: SOL Vault
+    #[account(mut, seeds = [b"vault"], bump)]
+    pub sol_vault: AccountInfo<'info>,
+    
+    /// Canonical GPU vault: the gpu_token_mint ATA of the gpu_vault PDA
+    /// (may be omitted until a GPU token is set)
+    #[account(
+        mut,
+        associated_token::mint = global_state.gpu_token_mint,
+        associated_token::authority = gpu_vault_authority,
+        associated_token::token_program = token_program,
+    )]
+    pub gpu_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    /// CHECK: GPU Vault Authority PDA
+    #[account(seeds = [b"gpu_vault"], bump)]
+    pub gpu_vault_authority: AccountInfo<'info>,
+    
+    /// User's GPU token account, required when GPU proceeds are paid
+    #[account(mut, token::mint = global_state.gpu_token_mint, token::authority = user)]
+    pub user_gpu_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> SellMiningPower<'info> {
+    // Pay SOL and GPU from the vaults to the seller
+    fn pay_from_vaults(&self, vault_bump: u8, gpu_vault_bump: u8, sol_amount: u64, gpu_amount: u64) -> Result<()> {
+        if sol_amount > 0 {
+            let signer_seeds: &[&[&[u8]]] = &[&[b"vault", &[vault_bump]]];
+            anchor_lang::system_program::transfer(
+                CpiContext::new_with_signer(
+                    self.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: self.sol_vault.to_account_info(),
+                        to: self.user.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                sol_amount,
+            )?;
+        }
+        
+        if gpu_amount > 0 {
+            let (Some(gpu_vault), Some(user_gpu_account)) = (self.gpu_vault.as_ref(), self.user_gpu_account.as_ref()) else {
+                return err!(ErrorCode::MissingGpuAccounts);
+            };
+            let signer_seeds: &[&[&[u8]]] = &[&[b"gpu_vault", &[gpu_vault_bump]]];
+            token::transfer(
+                CpiContext::new_with_signer(
+                    self.token_program.to_account_info(),
+                    SplTransfer {
+                        from: gpu_vault.to_account_info(),
+                        to: user_gpu_account.to_account_info(),
+                        authority: self.gpu_vault_authority.to_account_info(),
+                    },
+                    signer_seeds,
+                ),
+                gpu_amount,
+            )?;
+        }
+        
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct LockMiningPower<'info> {
+    #[account(mut, seeds = [b"global_state"], bump)]
+    pub global_state: Account<'info, GlobalState>,
+    
+    #[account(mut, seeds = [b"user_state", user.key().as_ref()], bump)]
+    pub user_state: Account<'info, UserState>,
+    
+    #[account(
+        init,
+        payer = user,
+        space = 8 + LockedPosition::INIT_SPACE,
+        seeds = [b"lock_position", user.key().as_ref(), user_state.next_lock_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub position: Account<'info, LockedPosition>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    /// CHECK: SOL Vault (for pool updates)
+    #[account(seeds = [b"vault"], bump)]
+    pub sol_vault: AccountInfo<'info>,
+    
+    /// Canonical GPU vault: the gpu_token_mint ATA of the gpu_vault PDA
+    /// (may be omitted until a GPU token is set)
+    #[account(
+        associated_token::mint = global_state.gpu_token_mint,
+        associated_token::authority = gpu_vault_authority,
+    )]
+    pub gpu_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    /// CHECK: GPU Vault Authority PDA
+    #[account(seeds = [b"gpu_vault"], bump)]
+    pub gpu_vault_authority: AccountInfo<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct HarvestPosition<'info> {
+    #[account(mut, seeds = [b"global_state"], bump)]
+    pub global_state: Account<'info, GlobalState>,
+    
+    #[account(mut, seeds = [b"user_state", user.key().as_ref()], bump)]
+    pub user_state: Account<'info, UserState>,
+    
+    #[account(mut, seeds = [b"lock_position", user.key().as_ref(), position.id.to_le_bytes().as_ref()], bump = position.bump)]
+    pub position: Account<'info, LockedPosition>,
+    
+    pub user: Signer<'info>,
+    
+    /// CHECK: SOL Vault (for pool updates)
+    #[account(seeds = [b"vault"], bump)]
+    pub sol_vault: AccountInfo<'info>,
+    
+    /// Canonical GPU vault: the gpu_token_mint ATA of the gpu_vault PDA
+    /// (may be omitted until a GPU token is set)
+    #[account(
+        associated_token::mint = global_state.gpu_token_mint,
+        associated_token::authority = gpu_vault_authority,
+    )]
+    pub gpu_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    /// CHECK: GPU Vault Authority PDA
+    #[account(seeds = [b"gpu_vault"], bump)]
+    pub gpu_vault_authority: AccountInfo<'info>,
 }
 
-// NEW PRICING: 1% of network hashrate = 2% of total TVL (in USD)
-// Calculate MH/s based on TVL percentage model
-fn calculate_mhs_for_usd(usd_amount: u128, total_mhs: u64, tvl_usd: u128) -> Result<u64> {
-    // 1% of hashrate costs 2% of TVL
-    // MH/s = (USD / TVL) × total_MH/s × 0.5
-    // Rearranged: MH/s = (USD × total_MH/s) / (TVL × 2)
+#[derive(Accounts)]
+pub struct UnlockPosition<'info> {
+    #[account(mut, seeds = [b"global_state"], bump)]
+    pub global_state: Account<'info, GlobalState>,
     
-    if tvl_usd == 0 || total_mhs == 0 {
-        return Ok(0);
-    }
+    #[account(mut, seeds = [b"user_state", user.key().as_ref()], bump)]
+    pub user_state: Account<'info, UserState>,
     
-    let numerator = usd_amount.checked_mul(total_mhs as u128).ok_or(ErrorCode::Overflow)?;
-    let denominator = tvl_usd.checked_mul(2).ok_or(ErrorCode::Overflow)?;
-    let mhs = numerator.checked_div(denominator).ok_or(ErrorCode::DivisionByZero)?;
+    #[account(
+        mut,
+        close = user,
+        seeds = [b"lock_position", user.key().as_ref(), position.id.to_le_bytes().as_ref()],
+        bump = position.bump
+    )]
+    pub position: Account<'info, LockedPosition>,
     
-    u64::try_from(mhs).map_err(|_| ErrorCode::Overflow.into())
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    /// CHECK: SOL Vault (for pool updates)
+    #[account(seeds = [b"vault"], bump)]
+    pub sol_vault: AccountInfo<'info>,
+    
+    /// Canonical GPU vault: the gpu_token_mint ATA of the gpu_vault PDA
+    /// (may be omitted until a GPU token is set)
+    #[account(
+        associated_token::mint = global_state.gpu_token_mint,
+        associated_token::authority = gpu_vault_authority,
+    )]
+    pub gpu_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    /// CHECK: GPU Vault Authority PDA
+    #[account(seeds = [b"gpu_vault"], bump)]
+    pub gpu_vault_authority: AccountInfo<'info>,
 }
 
-fn calculate_earnings(
-    user_mhs: u64,
-    total_mhs: u64,
-    last_claim: i64,
-    current_time: i64,
-    vault_balance: u64,
-    daily_percentage: u8
-) -> Result<u64> {
-    if total_mhs == 0 {
-        return Ok(0);
-    }
+#[derive(Accounts)]
+pub struct RepairRigs<'info> {
+    #[account(mut, seeds = [b"global_state"], bump)]
+    pub global_state: Account<'info, GlobalState>,
     
-    let time_passed = (current_time - last_claim) as u64;
-    let seconds_in_day = 86_400u64;
+    #[account(mut, seeds = [b"user_state", user.key().as_ref()], bump)]
+    pub user_state: Account<'info, UserState>,
     
-    // User's share of total mining power
-    let user_share_numerator = (user_mhs as u128).checked_mul(1_000_000).ok_or(ErrorCode::Overflow)?;
-    let user_share = user_share_numerator.checked_div(total_mhs as u128).ok_or(ErrorCode::DivisionByZero)?;
+    #[account(mut)]
+    pub user: Signer<'info>,
     
-    // Daily pool = daily_percentage% of vault
-    let daily_pool = (vault_balance as u128)
-        .checked_mul(daily_percentage as u128)
-        .ok_or(ErrorCode::Overflow)?
-        .checked_div(100)
-        .ok_or(ErrorCode::DivisionByZero)?;
+    /// CHECK: SOL Vault, receives SOL repairs
+    #[account(mut, seeds = [b"vault"], bump)]
+    pub sol_vault: AccountInfo<'info>,
     
-    // Earnings = user's share of pool, pro-rated by time
-    let earnings = daily_pool
-        .checked_mul(user_share)
-        .ok_or(ErrorCode::Overflow)?
-        .checked_div(1_000_000)
-        .ok_or(ErrorCode::DivisionByZero)?
-        .checked_mul(time_passed as u128)
-        .ok_or(ErrorCode::Overflow)?
-        .checked_div(seconds_in_day as u128)
-        .ok_or(ErrorCode::DivisionByZero)?;
+    /// Canonical GPU vault: the gpu_token_mint ATA of the gpu_vault PDA
+    /// (may be omitted until a GPU token is set)
+    #[account(
+        mut,
+        associated_token::mint = global_state.gpu_token_mint,
+        associated_token::authority = gpu_vault_authority,
+        associated_token::token_program = token_program,
+    )]
+    pub gpu_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    /// CHECK: GPU Vault Authority PDA
+    #[account(seeds = [b"gpu_vault"], bump)]
+    pub gpu_vault_authority: AccountInfo<'info>,
+    
+    /// User's GPU token account, required for GPU repairs
+    #[account(mut, token::mint = global_state.gpu_token_mint, token::authority = user)]
+    pub user_gpu_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreateRig<'info> {
+    #[account(mut, seeds = [b"global_state"], bump)]
+    pub global_state: Account<'info, GlobalState>,
+    
+    #[account(mut, seeds = [b"user_state", user.key().as_ref()], bump)]
+    pub user_state: Account<'info, UserState>,
+    
+    #[account(
+        init,
+        payer = user,
+        space = 8 + RigPosition::INIT_SPACE,
+        seeds = [b"rig", global_state.next_rig_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub rig: Account<'info, RigPosition>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    /// CHECK: SOL Vault (for pool updates)
+    #[account(seeds = [b"vault"], bump)]
+    pub sol_vault: AccountInfo<'info>,
+    
+    /// Canonical GPU vault: the gpu_token_mint ATA of the gpu_vault PDA
+    /// (may be omitted until a GPU token is set)
+    #[account(
+        associated_token::mint = global_state.gpu_token_mint,
+        associated_token::authority = gpu_vault_authority,
+    )]
+    pub gpu_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    /// CHECK: GPU Vault Authority PDA
+    #[account(seeds = [b"gpu_vault"], bump)]
+    pub gpu_vault_authority: AccountInfo<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct TokenizeRig<'info> {
+    #[account(seeds = [b"global_state"], bump)]
+    pub global_state: Account<'info, GlobalState>,
+    
+    #[account(mut, seeds = [b"rig", rig.id.to_le_bytes().as_ref()], bump = rig.bump, constraint = rig.owner == owner.key() @ ErrorCode::Unauthorized)]
+    pub rig: Account<'info, RigPosition>,
+    
+    #[account(
+        init,
+        payer = owner,
+        seeds = [b"rig_mint", rig.key().as_ref()],
+        bump,
+        mint::decimals = 0,
+        mint::authority = rig,
+        mint::token_program = token_program,
+    )]
+    pub nft_mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(
+        init,
+        payer = owner,
+        associated_token::mint = nft_mint,
+        associated_token::authority = owner,
+        associated_token::token_program = token_program,
+    )]
+    pub owner_nft_account: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct TransferRig<'info> {
+    #[account(mut, seeds = [b"global_state"], bump)]
+    pub global_state: Account<'info, GlobalState>,
+    
+    /// Current owner's account, receives the rig's settled rewards
+    #[account(mut, seeds = [b"user_state", owner.key().as_ref()], bump)]
+    pub user_state: Account<'info, UserState>,
+    
+    #[account(mut, seeds = [b"rig", rig.id.to_le_bytes().as_ref()], bump = rig.bump, constraint = rig.owner == owner.key() @ ErrorCode::Unauthorized)]
+    pub rig: Account<'info, RigPosition>,
+    
+    pub owner: Signer<'info>,
+    
+    /// CHECK: SOL Vault (for pool updates)
+    #[account(seeds = [b"vault"], bump)]
+    pub sol_vault: AccountInfo<'info>,
+    
+    /// Canonical GPU vault: the gpu_token_mint ATA of the gpu_vault PDA
+    /// (may be omitted until a GPU token is set)
+    #[account(
+        associated_token::mint = global_state.gpu_token_mint,
+        associated_token::authority = gpu_vault_authority,
+    )]
+    pub gpu_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    /// CHECK: GPU Vault Authority PDA
+    #[account(seeds = [b"gpu_vault"], bump)]
+    pub gpu_vault_authority: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CompoundRig<'info> {
+    #[account(mut, seeds = [b"global_state"], bump)]
+    pub global_state: Account<'info, GlobalState>,
+    
+    #[account(mut, seeds = [b"rig", rig.id.to_le_bytes().as_ref()], bump = rig.bump)]
+    pub rig: Account<'info, RigPosition>,
+    
+    pub holder: Signer<'info>,
+    
+    /// Holder's NFT token account, required for NFT-backed rigs
+    pub holder_nft_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    /// CHECK: SOL Vault (for pool updates)
+    #[account(seeds = [b"vault"], bump)]
+    pub sol_vault: AccountInfo<'info>,
+    
+    /// Canonical GPU vault: the gpu_token_mint ATA of the gpu_vault PDA
+    /// (may be omitted until a GPU token is set)
+    #[account(
+        associated_token::mint = global_state.gpu_token_mint,
+        associated_token::authority = gpu_vault_authority,
+    )]
+    pub gpu_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    /// CHECK: GPU Vault Authority PDA
+    #[account(seeds = [b"gpu_vault"], bump)]
+    pub gpu_vault_authority: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct HarvestRig<'info> {
+    #[account(mut, seeds = [b"global_state"], bump)]
+    pub global_state: Account<'info, GlobalState>,
+    
+    #[account(mut, seeds = [b"user_state", holder.key().as_ref()], bump)]
+    pub user_state: Account<'info, UserState>,
+    
+    #[account(mut, seeds = [b"rig", rig.id.to_le_bytes().as_ref()], bump = rig.bump)]
+    pub rig: Account<'info, RigPosition>,
+    
+    pub holder: Signer<'info>,
+    
+    /// Holder's NFT token account, required for NFT-backed rigs
+    pub holder_nft_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    /// CHECK: SOL Vault (for pool updates)
+    #[account(seeds = [b"vault"], bump)]
+    pub sol_vault: AccountInfo<'info>,
+    
+    /// Canonical GPU vault: the gpu_token_mint ATA of the gpu_vault PDA
+    /// (may be omitted until a GPU token is set)
+    #[account(
+        associated_token::mint = global_state.gpu_token_mint,
+        associated_token::authority = gpu_vault_authority,
+    )]
+    pub gpu_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    /// CHECK: GPU Vault Authority PDA
+    #[account(seeds = [b"gpu_vault"], bump)]
+    pub gpu_vault_authority: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MergeRig<'info> {
+    #[account(mut, seeds = [b"global_state"], bump)]
+    pub global_state: Account<'info, GlobalState>,
+    
+    #[account(mut, seeds = [b"user_state", holder.key().as_ref()], bump)]
+    pub user_state: Account<'info, UserState>,
+    
+    #[account(mut, seeds = [b"rig", rig.id.to_le_bytes().as_ref()], bump = rig.bump, close = holder)]
+    pub rig: Account<'info, RigPosition>,
+    
+    #[account(mut)]
+    pub holder: Signer<'info>,
+    
+    /// Holder's NFT token account, required for NFT-backed rigs
+    #[account(mut)]
+    pub holder_nft_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    /// The rig's NFT mint, required for NFT-backed rigs
+    #[account(mut)]
+    pub nft_mint: Option<InterfaceAccount<'info, Mint>>,
+    
+    /// CHECK: SOL Vault (for pool updates)
+    #[account(seeds = [b"vault"], bump)]
+    pub sol_vault: AccountInfo<'info>,
+    
+    /// Canonical GPU vault: the gpu_token_mint ATA of the gpu_vault PDA
+    /// (may be omitted until a GPU token is set)
+    #[account(
+        associated_token::mint = global_state.gpu_token_mint,
+        associated_token::authority = gpu_vault_authority,
+    )]
+    pub gpu_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    /// CHECK: GPU Vault Authority PDA
+    #[account(seeds = [b"gpu_vault"], bump)]
+    pub gpu_vault_authority: AccountInfo<'info>,
+    
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimReferralRewards<'info> {
+    #[account(mut, seeds = [b"global_state"], bump)]
+    pub global_state: Account<'info, GlobalState>,
+    
+    #[account(mut, seeds = [b"user_state", user.key().as_ref()], bump)]
+    pub user_state: Account<'info, UserState>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    /// CHECK: SOL Vault
+    #[account(mut, seeds = [b"vault"], bump)]
+    pub sol_vault: AccountInfo<'info>,
+    
+    /// Canonical GPU vault: the gpu_token_mint ATA of the gpu_vault PDA
+    /// (may be omitted until a GPU token is set)
+    #[account(
+        mut,
+        associated_token::mint = global_state.gpu_token_mint,
+        associated_token::authority = gpu_vault_authority,
+        associated_token::token_program = token_program,
+    )]
+    pub gpu_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    /// CHECK: GPU Vault Authority PDA
+    #[account(seeds = [b"gpu_vault"], bump)]
+    pub gpu_vault_authority: AccountInfo<'info>,
+    
+    /// User's GPU token account, required when GPU referral rewards are paid
+    #[account(mut, token::mint = global_state.gpu_token_mint, token::authority = user)]
+    pub user_gpu_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePrices<'info> {
+    #[account(mut, seeds = [b"global_state"], bump, constraint = global_state.authority == authority.key())]
+    pub global_state: Account<'info, GlobalState>,
+    
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct QuoteView<'info> {
+    #[account(seeds = [b"global_state"], bump)]
+    pub global_state: Account<'info, GlobalState>,
+    
+    /// CHECK: SOL Vault (for TVL calculation)
+    #[account(seeds = [b"vault"], bump)]
+    pub sol_vault: AccountInfo<'info>,
+    
+    /// Canonical GPU vault: the gpu_token_mint ATA of the gpu_vault PDA
+    /// (may be omitted until a GPU token is set)
+    #[account(
+        associated_token::mint = global_state.gpu_token_mint,
+        associated_token::authority = gpu_vault_authority,
+    )]
+    pub gpu_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    /// CHECK: GPU Vault Authority PDA
+    #[account(seeds = [b"gpu_vault"], bump)]
+    pub gpu_vault_authority: AccountInfo<'info>,
+    
+    /// CHECK: SOL/USD price account, required when price_source is Pyth
+    #[account(address = global_state.sol_price_feed @ ErrorCode::InvalidPriceFeed)]
+    pub sol_price_feed: Option<UncheckedAccount<'info>>,
+    
+    /// CHECK: GPU/USD price account, required when price_source is Pyth
+    #[account(address = global_state.gpu_price_feed @ ErrorCode::InvalidPriceFeed)]
+    pub gpu_price_feed: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct UserView<'info> {
+    #[account(seeds = [b"global_state"], bump)]
+    pub global_state: Account<'info, GlobalState>,
+    
+    #[account(seeds = [b"user_state", user.key().as_ref()], bump)]
+    pub user_state: Account<'info, UserState>,
+    
+    /// CHECK: Wallet whose rewards are being viewed
+    pub user: AccountInfo<'info>,
+    
+    /// CHECK: SOL Vault (for pool updates)
+    #[account(seeds = [b"vault"], bump)]
+    pub sol_vault: AccountInfo<'info>,
+    
+    /// Canonical GPU vault: the gpu_token_mint ATA of the gpu_vault PDA
+    /// (may be omitted until a GPU token is set)
+    #[account(
+        associated_token::mint = global_state.gpu_token_mint,
+        associated_token::authority = gpu_vault_authority,
+    )]
+    pub gpu_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    /// CHECK: GPU Vault Authority PDA
+    #[account(seeds = [b"gpu_vault"], bump)]
+    pub gpu_vault_authority: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeAuthority<'info> {
+    #[account(mut, seeds = [b"global_state"], bump, constraint = global_state.authority == authority.key())]
+    pub global_state: Account<'info, GlobalState>,
     
-    u64::try_from(earnings).map_err(|_| ErrorCode::Overflow.into())
+    pub authority: Signer<'info>,
 }
 
-// Rest of structs...
 #[derive(Accounts)]
-pub struct Initialize<'info> {
+pub struct AcceptAuthority<'info> {
     #[account(
-        init,
-        payer = authority,
-        space = 8 + GlobalState::INIT_SPACE,
+        mut,
         seeds = [b"global_state"],
-        bump
+        bump,
+        constraint = global_state.pending_authority == Some(new_authority.key()) @ ErrorCode::Unauthorized
     )]
     pub global_state: Account<'info, GlobalState>,
     
-    #[account(mut)]
-    pub authority: Signer<'info>,
-    
-    /// CHECK: Vault
-    #[account(mut, seeds = [b"vault"], bump)]
-    pub vault: AccountInfo<'info>,
+    pub new_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RenounceAuthority<'info> {
+    #[account(mut, seeds = [b"global_state"], bump, constraint = global_state.authority == authority.key())]
+    pub global_state: Account<'info, GlobalState>,
     
-    pub system_program: Program<'info, System>,
+    pub authority: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct InitUser<'info> {
+pub struct SetPaused<'info> {
     #[account(
-        init,
-        payer = user,
-        space = 8 + UserState::INIT_SPACE,
-        seeds = [b"user_state", user.key().as_ref()],
-        bump
+        mut,
+        seeds = [b"global_state"],
+        bump,
+        constraint = signer.key() == global_state.pauser || signer.key() == global_state.authority @ ErrorCode::Unauthorized
     )]
-    pub user_state: Account<'info, UserState>,
-    
-    #[account(mut)]
-    pub user: Signer<'info>,
+    pub global_state: Account<'info, GlobalState>,
     
-    pub system_program: Program<'info, System>,
+    pub signer: Signer<'info>,
 }
 
 #[derive(Accounts)]
-pub struct BuyWithGpu<'info> {
-    #[account(mut, seeds = [b"global_state"], bump)]
+pub struct SetPauser<'info> {
+    #[account(mut, seeds = [b"global_state"], bump, constraint = global_state.authority == authority.key())]
     pub global_state: Account<'info, GlobalState>,
     
-    #[account(mut, seeds = [b"user_state", buyer.key().as_ref()], bump)]
-    pub user_state: Account<'info, UserState>,
-    
-    #[account(mut)]
-    pub buyer: Signer<'info>,
-    
-    /// CHECK: SOL Vault (for TVL calculation)
-    #[account(mut, seeds = [b"vault"], bump)]
-    pub sol_vault: AccountInfo<'info>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateGlobalState<'info> {
+    /// CHECK: Deserialized by hand after growing, legacy layouts can't be loaded as `Account`
+    #[account(mut, seeds = [b"global_state"], bump, owner = crate::ID)]
+    pub global_state: UncheckedAccount<'info>,
     
     #[account(mut)]
-    pub gpu_vault: InterfaceAccount<'info, TokenAccount>,
+    pub authority: Signer<'info>,
     
-    #[account(mut)]
-    pub buyer_gpu_account: InterfaceAccount<'info, TokenAccount>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Where buys take their USD prices from
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum PriceSource {
+    Manual, // Admin-pushed prices via update_prices
+    Pyth, // Pyth PriceUpdateV2 accounts read on every buy
+}
+
+// Pyth `PriceUpdateV2` layout (pyth-solana-receiver-sdk), mirrored so the
+// program doesn't need the SDK. Mock feeds only have to match these bytes.
+#[derive(AnchorDeserialize)]
+struct PythPriceUpdate {
+    _write_authority: Pubkey,
+    verification_level: PythVerificationLevel,
+    price_message: PythPriceMessage,
+    _posted_slot: u64,
+}
+
+#[derive(AnchorDeserialize, PartialEq, Eq)]
+enum PythVerificationLevel {
+    Partial { _num_signatures: u8 },
+    Full,
+}
+
+#[derive(AnchorDeserialize)]
+struct PythPriceMessage {
+    _feed_id: [u8; 32],
+    price: i64,
+    conf: u64,
+    exponent: i32,
+    publish_time: i64,
+    _prev_publish_time: i64,
+    _ema_price: i64,
+    _ema_conf: u64,
+}
+
+/// How referral rewards are paid
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum ReferralRewardMode {
+    MiningPower, // Bonus MH/s added to total_mining_power
+    Fees, // Deposit currency carved out of the buy's protocol fee
+}
+
+/// A priced sell, see `quote_sell`
+struct SellQuote {
+    sol_payout: u64, // Lamports after the exit fee
+    sol_fee: u64,
+    gpu_payout: u64, // GPU after the exit fee
+    gpu_fee: u64,
+}
+
+/// The buy a referral chain is paid from
+struct ReferralPurchase {
+    currency: PaymentCurrency,
+    amount: u64, // Lamports or GPU deposited
+    mhs_bought: u64, // MH/s credited to the buyer
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum PaymentCurrency {
+    Sol,
+    Gpu,
+}
+
+/// Return data of `get_mhs_quote`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MhsQuote {
+    pub usd_value: u64, // Value of the deposit net of the protocol fee, in USD with 8 decimals
+    pub penalty_usd: u64, // USD the GPU penalty takes off the priced value (0 for SOL)
+    pub fee_amount: u64, // Lamports or GPU taken as protocol fee, not priced
+    pub mhs_after_fee: u64, // MH/s credited to the buyer
+}
+
+/// Return data of `get_pending_rewards`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PendingRewards {
+    pub hash: u64,
+    pub sol: u64,
+    pub gpu: u64,
+}
+
+/// Return data of `get_claim_preview`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ClaimPreview {
+    pub sol_gross: u64,
+    pub sol_fee: u64,
+    pub sol_payout: u64,
+    pub gpu_gross: u64,
+    pub gpu_fee: u64,
+    pub gpu_payout: u64,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct GlobalState {
+    pub authority: Pubkey,
+    pub dev_wallet: Pubkey, // Team address on record; no instruction pays it (fees go to the treasury or fee split), kept for account layout
+    pub total_mining_power: u64,
+    pub total_unclaimed_sol: u64, // Track unclaimed SOL earnings
+    pub total_unclaimed_gpu: u64, // Track unclaimed GPU earnings
+    pub initialized: bool,
+    pub daily_pool_percentage: u8, // % of TVL mineable per day (before the emission schedule steps it down)
+    pub base_buy_rate: u64, // MH/s per SOL at TVL=1
+    pub protocol_fee_val: u8,
+    pub gpu_penalty_bps: u16, // 15% = 1500 basis points
+    pub sol_usd_price: u64, // SOL price in USD with 8 decimals
+    pub gpu_usd_price: u64, // GPU price in USD with 8 decimals
+    pub gpu_token_mint: Pubkey, // GPU token mint address (configurable)
+    // Fields below were appended after launch; `migrate_global_state` grows the old account.
+    // A zeroed field must mean "off" so migrated deployments keep their old behaviour.
+    pub genesis_mhs_threshold: u64, // Genesis pricing ends at this hashrate (0 = ignored)
+    pub genesis_tvl_threshold_usd: u64, // ...or at this TVL in USD with 8 decimals (0 = ignored, both 0 = genesis off)
+    pub protocol_seed_sol: u64, // Protocol-owned lamports seeded at initialize
+    pub protocol_seed_gpu: u64, // Protocol-owned GPU tokens seeded at initialize
+    pub acc_sol_per_mhs: u128, // Lamports released per unit of reward weight at decay index 1, scaled by ACC_PRECISION
+    pub acc_gpu_per_mhs: u128, // GPU released per unit of reward weight at decay index 1, scaled by ACC_PRECISION
+    pub last_reward_update: i64, // Last time pool rewards were released
+    pub price_source: PriceSource, // Manual (update_prices) or Pyth price accounts
+    pub sol_price_feed: Pubkey, // Pyth SOL/USD price account
+    pub gpu_price_feed: Pubkey, // Pyth GPU/USD price account
+    pub max_price_age_secs: u32, // Oldest Pyth price a buy will accept
+    pub max_confidence_bps: u16, // Widest oracle confidence interval, as bps of price
+    pub price_updated_slot: u64, // Slot of the last price update (manual or oracle)
+    pub price_updated_at: i64, // Time of the last price update (manual or oracle)
+    pub max_price_move_bps: u16, // Largest move update_prices may make per window without an override (0 = no cap)
+    pub total_sol_fees: u64, // Cumulative SOL fees paid to the treasury or fee split
+    pub total_gpu_fees: u64, // Cumulative GPU fees paid to the treasury or fee split
+    pub total_buyback_sol: u64, // Cumulative treasury SOL withdrawn for GPU buybacks
+    pub total_gpu_burned: u64, // Cumulative GPU burned from the treasury
+    pub referral_levels: u8, // Leading entries of referral_bps that pay out (0 = legacy single 5% level)
+    pub referral_bps: [u16; MAX_REFERRAL_LEVELS], // Share of each buy paid per referral level, direct referrer first
+    pub referral_reward_mode: ReferralRewardMode, // MiningPower mints bonus MH/s; Fees pays the deposit currency
+    pub next_proposal_id: u64, // Id (and PDA seed) of the next admin proposal
+    pub timelock_delay_secs: u32, // Delay before a proposal may execute (0 = DEFAULT_TIMELOCK_DELAY_SECS)
+    pub pauser: Pubkey, // May set pause bits alongside the authority (default = authority only)
+    pub paused: u8, // PAUSE_* bits currently set
+    pub pending_authority: Option<Pubkey>, // Proposed authority waiting for accept_authority
+    pub fee_split_count: u8, // Leading fee_split entries in use (0 = all claim fees to the treasury)
+    pub fee_split: [FeeShare; MAX_FEE_RECIPIENTS], // Fee recipients and their bps, summing to 10,000
+    pub exit_fee_bps: u16, // Fee on sell_mining_power proceeds, left in the vaults (0 = DEFAULT_EXIT_FEE_BPS)
+    pub sell_cooldown_secs: u32, // Wait before sell proceeds can be claimed (0 = paid instantly)
+    pub total_locked_mhs: u64, // MH/s held in locked positions (part of total_mining_power)
+    pub total_lock_bonus: u64, // Extra reward weight from lock multipliers; pools share by total_mining_power + this
+    pub next_rig_id: u64, // Id (and PDA seed) of the next rig position
+    pub transfer_fee_bps: u16, // Share of transfer_mining_power burned as a fee (0 = free)
+    pub decay_bps_per_day: u16, // Daily wear on all MH/s (0 = off)
+    pub decay_index: u128, // Share of full condition left on never-repaired MH/s, scaled by DECAY_INDEX_ONE (0 = not started)
+    pub last_decay_update: i64, // Last time decay_index was advanced
+    pub total_rig_mhs: u64, // MH/s held in rigs (part of total_mining_power)
+    pub repair_lamports_per_mhs: u64, // SOL price of restoring 1 MH/s (0 = SOL repairs off)
+    pub repair_gpu_per_mhs: u64, // GPU price of restoring 1 MH/s (0 = GPU repairs off)
+    pub difficulty: u64, // Hash paid per compounded MH/s (0 = HASH_PER_MHS)
+    pub difficulty_epoch_secs: u32, // Length of a difficulty epoch (0 = difficulty frozen)
+    pub target_growth_bps: u16, // Hashrate growth per epoch that keeps the difficulty unchanged
+    pub difficulty_epoch_start: i64, // When the current difficulty epoch began
+    pub epoch_start_mining_power: u64, // Full-condition total_mining_power when the current epoch began
+    pub emission_start: i64, // When the emission schedule's first epoch begins
+    pub emission_epoch_secs: u32, // Length of an emission epoch (0 = flat daily_pool_percentage)
+    pub emission_decay_bps: u16, // Share of the daily pool kept each epoch (5,000 = halving)
+    pub manual_price_max_age_secs: u32, // Oldest manual price a buy will accept (0 = unchecked)
+    pub buyback_wallet: Pubkey, // Only destination for treasury buyback SOL, set by proposal (default = withdrawals off)
+    pub price_window_start: i64, // Start of the current update_prices move window (0 = none yet)
+    pub window_sol_usd_price: u64, // SOL price when the move window started
+    pub window_gpu_usd_price: u64, // GPU price when the move window started
+}
+
+/// Fixed lock terms for `lock_mining_power`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum LockTerm {
+    Days30,
+    Days90,
+    Days180,
+}
+
+impl LockTerm {
+    fn duration_secs(self) -> i64 {
+        match self {
+            LockTerm::Days30 => 30 * SECONDS_PER_DAY,
+            LockTerm::Days90 => 90 * SECONDS_PER_DAY,
+            LockTerm::Days180 => 180 * SECONDS_PER_DAY,
+        }
+    }
     
-    /// CHECK: Optional referrer
-    #[account(mut)]
-    pub referrer_state: Option<Account<'info, UserState>>,
+    fn multiplier_bps(self) -> u16 {
+        match self {
+            LockTerm::Days30 => LOCK_30D_MULTIPLIER_BPS,
+            LockTerm::Days90 => LOCK_90D_MULTIPLIER_BPS,
+            LockTerm::Days180 => LOCK_180D_MULTIPLIER_BPS,
+        }
+    }
     
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
+    /// Reward weight of `mhs_amount` MH/s locked for this term, rounded down
+    fn reward_weight(self, mhs_amount: u64) -> Result<u64> {
+        let weight = (mhs_amount as u128)
+            .checked_mul(self.multiplier_bps() as u128)
+            .ok_or(ErrorCode::Overflow)?
+            / 10_000;
+        
+        u64::try_from(weight).map_err(|_| ErrorCode::Overflow.into())
+    }
+}
+
+/// MH/s locked for a fixed term, PDA of ["lock_position", owner, id]
+#[account]
+#[derive(InitSpace)]
+pub struct LockedPosition {
+    pub owner: Pubkey,
+    pub id: u64,
+    pub mining_power: u64, // MH/s locked
+    pub multiplier_bps: u16, // Reward-weight multiplier of the term
+    pub reward_weight: u64, // mining_power × multiplier, the position's share of the pools
+    pub locked_at: i64,
+    pub unlock_at: i64, // End of the term; no early unlock
+    pub last_hash_update: i64,
+    pub sol_reward_debt: u128, // Full-condition reward_weight × acc_sol_per_mhs at last settlement
+    pub gpu_reward_debt: u128, // Full-condition reward_weight × acc_gpu_per_mhs at last settlement
+    pub bump: u8,
+    pub rated_mining_power: u64, // Rated MH/s the locked power stood for, returned to the owner on unlock
+    pub decay_snapshot: u128, // decay_index mining_power and reward_weight were last worn to
+}
+
+/// Transferable MH/s position, PDA of ["rig", id]. Optionally backed by a 1-of-1
+/// NFT, in which case the NFT holder controls it.
+#[account]
+#[derive(InitSpace)]
+pub struct RigPosition {
+    pub id: u64,
+    pub owner: Pubkey, // Owner, or the last NFT holder to act on an NFT-backed rig
+    pub nft_mint: Option<Pubkey>, // Set by tokenize_rig
+    pub mining_power: u64,
+    pub unclaimed_hash: u64, // Hash the rig has banked for compound_rig
+    pub last_hash_update: i64,
+    pub unclaimed_sol: u64, // Rewards banked on the rig, moved to the holder by harvest_rig
+    pub unclaimed_gpu: u64,
+    pub sol_reward_debt: u128, // Full-condition mining_power × acc_sol_per_mhs at last settlement
+    pub gpu_reward_debt: u128, // Full-condition mining_power × acc_gpu_per_mhs at last settlement
+    pub created_at: i64,
+    pub bump: u8,
+    pub rated_mining_power: u64, // Rated MH/s the rig's power stands for, returned to the holder by merge_rig
+    pub decay_snapshot: u128, // decay_index mining_power was last worn to
+}
+
+/// One row of the fee split table
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq, InitSpace)]
+pub struct FeeShare {
+    pub recipient: Pubkey, // Wallet or PDA; GPU fees go to its gpu_token_mint ATA
+    pub bps: u16,
+}
+
+/// Admin actions that must wait out the timelock
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace)]
+pub enum AdminAction {
+    DrainVault { amount: u64 }, // Lamports from the SOL vault to the authority
+    SetGpuToken { gpu_token_mint: Pubkey },
+    SetTimelockDelay { delay_secs: u32 },
+    SetBuybackWallet { buyback_wallet: Pubkey },
+    WithdrawBuybackSol { amount: u64 }, // Lamports from the treasury to the buyback wallet
+    ResetUserPower { user: Pubkey }, // Wipe a user's wallet MH/s and release everything reserved for them (not their lock positions or rigs)
+    ForcePrices { sol_usd_price: u64, gpu_usd_price: u64 }, // Manual prices past max_price_move_bps
+    SetMaxPriceMove { max_price_move_bps: u16 },
+    SetPriceOracle {
+        price_source: PriceSource,
+        sol_price_feed: Pubkey,
+        gpu_price_feed: Pubkey,
+        max_price_age_secs: u32,
+        max_confidence_bps: u16,
+        manual_max_age_secs: u32,
+    },
+    SetFeeSplit {
+        #[max_len(MAX_FEE_RECIPIENTS)]
+        shares: Vec<FeeShare>,
+    },
+    SetSellParams { exit_fee_bps: u16, cooldown_secs: u32 },
+    SetDevWallet { dev_wallet: Pubkey }, // Update the recorded team address; give it a SetFeeSplit share to pay it
+    SetReferralSchedule {
+        #[max_len(MAX_REFERRAL_LEVELS)]
+        level_bps: Vec<u16>,
+    },
+    SetReferralRewardMode { mode: ReferralRewardMode },
+    SetGenesisThresholds { mhs_threshold: u64, tvl_threshold_usd: u64 },
+    SetTransferFee { transfer_fee_bps: u16 },
+    SetDecayParams { decay_bps_per_day: u16, repair_lamports_per_mhs: u64, repair_gpu_per_mhs: u64 },
+    SetDifficultyParams { epoch_secs: u32, target_growth_bps: u16 },
+    SetEmissionSchedule { start: i64, epoch_secs: u32, decay_bps: u16 },
+    UpdateConfig { daily_pool_percentage: u8, protocol_fee_val: u8, gpu_penalty_bps: u16, base_buy_rate: u64 },
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum ProposalStatus {
+    Pending,
+    Executed,
+    Cancelled,
+}
+
+/// Queued admin action, PDA of ["proposal", id]. Kept after execution as a public record.
+#[account]
+#[derive(InitSpace)]
+pub struct Proposal {
+    pub id: u64,
+    pub proposer: Pubkey,
+    pub action: AdminAction,
+    #[max_len(MAX_PROPOSAL_DESCRIPTION_LEN)]
+    pub description: String,
+    pub created_at: i64,
+    pub eta: i64, // Earliest time execute_proposal may run
+    pub status: ProposalStatus,
+    pub bump: u8,
+}
+
+/// Protocol treasury PDA: collects claim fees for GPU buyback and burn
+#[account]
+#[derive(InitSpace)]
+pub struct Treasury {
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct UserState {
+    pub owner: Pubkey,
+    pub mining_power: u64,
+    pub unclaimed_sol: u64, // Track user's unclaimed SOL (lamports)
+    pub unclaimed_gpu: u64, // Track user's unclaimed GPU
+    pub last_claim: i64,
+    pub referrer: Option<Pubkey>,
+    pub total_sol_claimed: u64, // Total SOL claimed all-time
+    pub total_gpu_claimed: u64, // Total GPU tokens claimed all-time
+    // Fields below were appended after launch; `migrate_user_state` grows old accounts
+    pub unclaimed_hash: u64, // Banked hash waiting to be compounded
+    pub last_hash_update: i64, // Last time hash was banked
+    pub sol_reward_debt: u128, // Full-condition mining_power × acc_sol_per_mhs at last settlement
+    pub gpu_reward_debt: u128, // Full-condition mining_power × acc_gpu_per_mhs at last settlement
+    pub referral_sol_earned: u64, // Fee-funded referral SOL waiting for claim_referral_rewards
+    pub referral_gpu_earned: u64, // Fee-funded referral GPU waiting for claim_referral_rewards
+    pub pending_sell_sol: u64, // Sell proceeds waiting out the cooldown
+    pub pending_sell_gpu: u64,
+    pub sell_unlock_at: i64, // When claim_sell_proceeds may pay the pending proceeds
+    pub next_lock_id: u64, // Id (and PDA seed) of the user's next locked position
+    pub locked_mining_power: u64, // Rated MH/s in the user's locked positions, not part of mining_power
+    pub rated_mining_power: u64, // Full-condition MH/s that repair_rigs restores mining_power to
+    pub decay_snapshot: u128, // decay_index mining_power was last worn to (0 = never)
+}
+
+#[event]
+pub struct Initialized {
+    pub authority: Pubkey,
+    pub dev_wallet: Pubkey,
+    pub gpu_token_mint: Pubkey,
+    pub seed_sol: u64,
+    pub seed_gpu: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MiningPowerBought {
+    pub user: Pubkey,
+    pub currency: PaymentCurrency,
+    pub amount: u64, // Lamports or GPU tokens deposited
+    pub usd_value: u64,
+    pub penalty_usd: u64,
+    pub fee_amount: u64, // Lamports or GPU taken as protocol fee
+    pub mhs_bought: u64, // MH/s credited for the deposit net of the protocol fee
+    pub referrer: Option<Pubkey>,
+    pub referral_bonus: u64, // MH/s paid across all referral levels (see ReferralPaid)
+    pub referral_reward: u64, // Lamports or GPU paid across all referral levels out of the buy fee
+    pub mining_power: u64, // Buyer's MH/s after the buy
+    pub total_mining_power: u64,
+    pub settled_hash: u64, // Hash banked for the buyer before the buy
+    pub settled_sol: u64, // SOL banked for the buyer before the buy
+    pub settled_gpu: u64, // GPU banked for the buyer before the buy
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferralPaid {
+    pub buyer: Pubkey,
+    pub referrer: Pubkey,
+    pub level: u8, // 1 = direct referrer
+    pub bps: u16,
+    pub bonus: u64, // MH/s credited to the referrer (MiningPower mode)
+    pub currency: PaymentCurrency,
+    pub reward: u64, // Lamports or GPU credited to the referrer (Fees mode)
+    pub referrer_mining_power: u64, // Referrer's MH/s after the payment
+    pub settled_hash: u64, // Hash banked for the referrer before the bonus (MiningPower mode)
+    pub settled_sol: u64, // SOL banked for the referrer before the bonus (MiningPower mode)
+    pub settled_gpu: u64, // GPU banked for the referrer before the bonus (MiningPower mode)
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct HashCompounded {
+    pub user: Pubkey,
+    pub hash_spent: u64,
+    pub mhs_added: u64,
+    pub unclaimed_hash: u64, // Hash left banked after compounding
+    pub difficulty: u64, // Hash paid per MH/s
+    pub mining_power: u64,
+    pub total_mining_power: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MiningPowerTransferred {
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub amount: u64, // MH/s taken from the sender
+    pub fee: u64, // MH/s burned
+    pub received: u64,
+    pub sender_mining_power: u64,
+    pub recipient_mining_power: u64,
+    pub total_mining_power: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MiningPowerSold {
+    pub user: Pubkey,
+    pub mhs_sold: u64,
+    pub sol_payout: u64, // Lamports after the exit fee
+    pub sol_fee: u64,
+    pub gpu_payout: u64, // GPU after the exit fee
+    pub gpu_fee: u64,
+    pub unlock_at: i64, // When queued proceeds can be claimed (0 = paid now)
+    pub mining_power: u64, // Seller's MH/s after the sell
+    pub total_mining_power: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SellProceedsClaimed {
+    pub user: Pubkey,
+    pub sol_payout: u64,
+    pub gpu_payout: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MiningPowerLocked {
+    pub user: Pubkey,
+    pub position_id: u64,
+    pub mhs_locked: u64,
+    pub multiplier_bps: u16,
+    pub reward_weight: u64,
+    pub unlock_at: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PositionHarvested {
+    pub user: Pubkey,
+    pub position_id: u64,
+    pub hash: u64,
+    pub sol: u64,
+    pub gpu: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PositionUnlocked {
+    pub user: Pubkey,
+    pub position_id: u64,
+    pub mhs_unlocked: u64,
+    pub mining_power: u64, // Owner's unlocked MH/s afterwards
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RigsRepaired {
+    pub user: Pubkey,
+    pub currency: PaymentCurrency,
+    pub mhs_restored: u64,
+    pub cost: u64, // Lamports or GPU paid into the vault
+    pub mining_power: u64,
+    pub total_mining_power: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RigCreated {
+    pub rig_id: u64,
+    pub owner: Pubkey,
+    pub mining_power: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RigTokenized {
+    pub rig_id: u64,
+    pub owner: Pubkey,
+    pub nft_mint: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RigTransferred {
+    pub rig_id: u64,
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub settled_hash: u64, // Moved to the previous owner's ledgers
+    pub settled_sol: u64,
+    pub settled_gpu: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RigCompounded {
+    pub rig_id: u64,
+    pub owner: Pubkey,
+    pub hash_spent: u64,
+    pub mhs_added: u64,
+    pub difficulty: u64, // Hash paid per MH/s
+    pub mining_power: u64, // Rig's MH/s after compounding
+    pub total_mining_power: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RigHarvested {
+    pub rig_id: u64,
+    pub owner: Pubkey,
+    pub sol: u64,
+    pub gpu: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RigMerged {
+    pub rig_id: u64,
+    pub owner: Pubkey,
+    pub mining_power: u64, // MH/s returned to the owner's user account
+    pub settled_hash: u64, // Moved to the owner's ledgers
+    pub settled_sol: u64,
+    pub settled_gpu: u64,
+    pub user_mining_power: u64, // Owner's MH/s after the merge
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct EarningsClaimed {
+    pub user: Pubkey,
+    pub sol_payout: u64,
+    pub sol_fee: u64,
+    pub gpu_payout: u64,
+    pub gpu_fee: u64,
+    pub total_sol_claimed: u64,
+    pub total_gpu_claimed: u64,
+    pub mining_power: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct UserInitialized {
+    pub user: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct UserStateMigrated {
+    pub user: Pubkey,
+    pub size: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TreasuryInitialized {
+    pub treasury: Pubkey,
+    pub treasury_gpu_account: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct GpuBurned {
+    pub amount: u64,
+    pub total_gpu_burned: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BuybackWalletUpdated {
+    pub buyback_wallet: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BuybackSolWithdrawn {
+    pub buyback_wallet: Pubkey,
+    pub amount: u64,
+    pub total_buyback_sol: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct VaultDrained {
+    pub authority: Pubkey,
+    pub amount: u64,
+    pub vault_balance: u64, // Lamports left in the vault
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProposalCreated {
+    pub id: u64,
+    pub proposer: Pubkey,
+    pub action: AdminAction,
+    pub description: String,
+    pub eta: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProposalExecuted {
+    pub id: u64,
+    pub action: AdminAction,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProposalCancelled {
+    pub id: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TimelockDelayUpdated {
+    pub delay_secs: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct UserPowerReset {
+    pub user: Pubkey,
+    pub old_power: u64,
+    pub released_sol: u64, // Pending SOL returned to the mineable TVL
+    pub released_gpu: u64,
+    pub total_mining_power: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PricesUpdated {
+    pub sol_usd_price: u64,
+    pub gpu_usd_price: u64,
+    pub large_move_override: bool,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferralScheduleUpdated {
+    pub level_bps: Vec<u16>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferralRewardModeUpdated {
+    pub mode: ReferralRewardMode,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReferralRewardsClaimed {
+    pub user: Pubkey,
+    pub sol_amount: u64,
+    pub gpu_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuthorityProposed {
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuthorityTransferred {
+    pub previous_authority: Pubkey,
+    pub new_authority: Pubkey, // Pubkey::default() when renounced
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DevWalletUpdated {
+    pub previous_dev_wallet: Pubkey,
+    pub dev_wallet: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FeeSplitUpdated {
+    pub shares: Vec<FeeShare>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FeesDistributed {
+    pub currency: PaymentCurrency,
+    pub amount: u64,
+    pub recipients: Vec<Pubkey>,
+    pub amounts: Vec<u64>, // Per recipient; 0 where the cut went to the treasury
+    pub treasury_amount: u64, // Cuts of closed or missing recipient accounts, sent to the treasury
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PauseUpdated {
+    pub paused: u8,
+    pub by: Pubkey, // Pauser or authority that changed the bits
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PauserUpdated {
+    pub pauser: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MaxPriceMoveUpdated {
+    pub max_price_move_bps: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PriceOracleUpdated {
+    pub price_source: PriceSource,
+    pub sol_price_feed: Pubkey,
+    pub gpu_price_feed: Pubkey,
+    pub max_price_age_secs: u32,
+    pub max_confidence_bps: u16,
+    pub manual_max_age_secs: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ConfigUpdated {
+    pub daily_pool_percentage: u8,
+    pub protocol_fee_val: u8,
+    pub gpu_penalty_bps: u16,
+    pub base_buy_rate: u64,
+    pub timestamp: i64,
 }
 
-#[derive(Accounts)]
-pub struct BuyMiningPower<'info> {
-    #[account(mut, seeds = [b"global_state"], bump)]
-    pub global_state: Account<'info, GlobalState>,
-    
-    #[account(mut, seeds = [b"user_state", buyer.key().as_ref()], bump)]
-    pub user_state: Account<'info, UserState>,
-    
-    #[account(mut)]
-    pub buyer: Signer<'info>,
-    
-    /// CHECK: Vault
-    #[account(mut, seeds = [b"vault"], bump)]
-    pub vault: AccountInfo<'info>,
-    
-    /// CHECK: GPU Vault ATA (for TVL calculation only)
-    pub gpu_vault: AccountInfo<'info>,
-    
-    /// CHECK: Dev wallet
-    #[account(mut, address = global_state.dev_wallet)]
-    pub dev_wallet: AccountInfo<'info>,
-    
-    /// CHECK: Optional referrer (unchecked to allow null)
-    pub referrer_state: Option<AccountInfo<'info>>,
-    
-    pub system_program: Program<'info, System>,
+#[event]
+pub struct SellParamsUpdated {
+    pub exit_fee_bps: u16,
+    pub cooldown_secs: u32,
+    pub timestamp: i64,
 }
 
-#[derive(Accounts)]
-pub struct CompoundHash<'info> {
-    #[account(mut, seeds = [b"global_state"], bump)]
-    pub global_state: Account<'info, GlobalState>,
-    
-    #[account(mut, seeds = [b"user_state", user.key().as_ref()], bump)]
-    pub user_state: Account<'info, UserState>,
-    
-    #[account(mut)]
-    pub user: Signer<'info>,
+#[event]
+pub struct TransferFeeUpdated {
+    pub transfer_fee_bps: u16,
+    pub timestamp: i64,
 }
 
-#[derive(Accounts)]
-pub struct DrainVault<'info> {
-    #[account(mut, seeds = [b"global_state"], bump, constraint = global_state.authority == authority.key())]
-    pub global_state: Account<'info, GlobalState>,
-    
-    /// CHECK: Vault PDA
-    #[account(mut, seeds = [b"vault"], bump)]
-    pub vault: AccountInfo<'info>,
-    
-    #[account(mut)]
-    pub authority: Signer<'info>,
-    
-    pub system_program: Program<'info, System>,
+#[event]
+pub struct DecayParamsUpdated {
+    pub decay_bps_per_day: u16,
+    pub repair_lamports_per_mhs: u64,
+    pub repair_gpu_per_mhs: u64,
+    pub decay_index: u128,
+    pub timestamp: i64,
 }
 
-#[derive(Accounts)]
-pub struct ResetUser<'info> {
-    #[account(mut, seeds = [b"global_state"], bump, constraint = global_state.authority == authority.key())]
-    pub global_state: Account<'info, GlobalState>,
-    
-    #[account(mut)]
-    pub user_state: Account<'info, UserState>,
-    
-    pub authority: Signer<'info>,
+#[event]
+pub struct DifficultyAdjusted {
+    pub old_difficulty: u64,
+    pub new_difficulty: u64,
+    pub epoch_start_mining_power: u64, // Full-condition hashrate, like epoch_end_mining_power
+    pub epoch_end_mining_power: u64,
+    pub timestamp: i64,
 }
 
-#[derive(Accounts)]
-pub struct ClaimEarnings<'info> {
-    #[account(mut, seeds = [b"global_state"], bump)]
-    pub global_state: Account<'info, GlobalState>,
-    
-    #[account(mut, seeds = [b"user_state", user.key().as_ref()], bump)]
-    pub user_state: Account<'info, UserState>,
-    
-    #[account(mut)]
-    pub user: Signer<'info>,
-    
-    /// CHECK: SOL Vault
-    #[account(mut, seeds = [b"vault"], bump)]
-    pub sol_vault: AccountInfo<'info>,
-    
-    #[account(mut)]
-    pub gpu_vault: InterfaceAccount<'info, TokenAccount>,
-    
-    /// CHECK: GPU Vault Authority PDA
-    #[account(seeds = [b"gpu_vault"], bump)]
-    pub gpu_vault_authority: AccountInfo<'info>,
-    
-    #[account(mut)]
-    pub user_gpu_account: InterfaceAccount<'info, TokenAccount>,
-    
-    #[account(mut)]
-    pub dev_gpu_account: InterfaceAccount<'info, TokenAccount>,
-    
-    /// CHECK: Dev wallet
-    #[account(mut, address = global_state.dev_wallet)]
-    pub dev_wallet: AccountInfo<'info>,
-    
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
+#[event]
+pub struct DifficultyParamsUpdated {
+    pub epoch_secs: u32,
+    pub target_growth_bps: u16,
+    pub difficulty: u64,
+    pub timestamp: i64,
 }
 
-#[derive(Accounts)]
-pub struct UpdatePrices<'info> {
-    #[account(mut, seeds = [b"global_state"], bump, constraint = global_state.authority == authority.key())]
-    pub global_state: Account<'info, GlobalState>,
-    
-    pub authority: Signer<'info>,
+#[event]
+pub struct EmissionScheduleUpdated {
+    pub start: i64,
+    pub epoch_secs: u32,
+    pub decay_bps: u16,
+    pub timestamp: i64,
 }
 
-#[derive(Accounts)]
-pub struct SetGpuToken<'info> {
-    #[account(mut, seeds = [b"global_state"], bump, constraint = global_state.authority == authority.key())]
-    pub global_state: Account<'info, GlobalState>,
-    
-    pub authority: Signer<'info>,
+#[event]
+pub struct GenesisThresholdsUpdated {
+    pub mhs_threshold: u64,
+    pub tvl_threshold_usd: u64,
+    pub timestamp: i64,
 }
 
-#[account]
-#[derive(InitSpace)]
-pub struct GlobalState {
-    pub authority: Pubkey,
-    pub dev_wallet: Pubkey,
-    pub total_mining_power: u64,
-    pub total_unclaimed_sol: u64, // Track unclaimed SOL earnings
-    pub total_unclaimed_gpu: u64, // Track unclaimed GPU earnings
-    pub initialized: bool,
-    pub daily_pool_percentage: u8, // % of TVL mineable per day
-    pub base_buy_rate: u64, // MH/s per SOL at TVL=1
-    pub protocol_fee_val: u8,
-    pub gpu_penalty_bps: u16, // 15% = 1500 basis points
-    pub sol_usd_price: u64, // SOL price in USD with 8 decimals
-    pub gpu_usd_price: u64, // GPU price in USD with 8 decimals
-    pub gpu_token_mint: Pubkey, // GPU token mint address (configurable)
+#[event]
+pub struct GlobalStateMigrated {
+    pub size: u32,
+    pub timestamp: i64,
 }
 
-#[account]
-#[derive(InitSpace)]
-pub struct UserState {
-    pub owner: Pubkey,
-    pub mining_power: u64,
-    pub unclaimed_earnings: u64, // Track user's unclaimed SOL
-    pub unclaimed_gpu_earnings: u64, // Track user's unclaimed GPU
-    pub last_claim: i64,
-    pub referrer: Option<Pubkey>,
-    pub total_sol_claimed: u64, // Total SOL claimed all-time
-    pub total_gpu_claimed: u64, // Total GPU tokens claimed all-time
+#[event]
+pub struct GpuTokenUpdated {
+    pub gpu_token_mint: Pubkey,
+    pub timestamp: i64,
 }
 
 #[error_code]
@@ -845,4 +5575,769 @@ pub enum ErrorCode {
     InsufficientFunds,
     #[msg("Price not set")]
     PriceNotSet,
+    #[msg("Invalid owner")]
+    InvalidOwner,
+    #[msg("Unauthorized")]
+    Unauthorized,
+    #[msg("Missing GPU accounts")]
+    MissingGpuAccounts,
+    #[msg("Invalid mint")]
+    InvalidMint,
+    #[msg("Invalid price feed")]
+    InvalidPriceFeed,
+    #[msg("Missing price feed")]
+    MissingPriceFeed,
+    #[msg("Stale price")]
+    StalePrice,
+    #[msg("Price confidence too wide")]
+    PriceTooUncertain,
+    #[msg("Price move exceeds the per-window limit")]
+    PriceMoveTooLarge,
+    #[msg("GPU vault is not the canonical vault account")]
+    InvalidGpuVault,
+    #[msg("Referrer account does not match the stored referrer")]
+    InvalidReferrer,
+    #[msg("Referral schedule has too many levels or pays out too much")]
+    InvalidReferralSchedule,
+    #[msg("Proposal description is too long")]
+    DescriptionTooLong,
+    #[msg("Timelock delay is below the minimum")]
+    InvalidTimelockDelay,
+    #[msg("Proposal is not pending")]
+    ProposalNotPending,
+    #[msg("Proposal timelock has not passed")]
+    ProposalNotReady,
+    #[msg("This action is paused")]
+    Paused,
+    #[msg("Invalid authority")]
+    InvalidAuthority,
+    #[msg("Config value is outside its allowed range")]
+    ConfigOutOfBounds,
+    #[msg("Fee split needs 1-6 recipients with shares summing to 10,000 bps")]
+    InvalidFeeSplit,
+    #[msg("Fee split recipient accounts are missing")]
+    MissingFeeRecipients,
+    #[msg("Fee recipient account does not match the fee split")]
+    InvalidFeeRecipient,
+    #[msg("Sell proceeds are still in their cooldown")]
+    SellCooldownActive,
+    #[msg("Position is still locked")]
+    PositionLocked,
+    #[msg("Cannot transfer mining power to yourself")]
+    InvalidRecipient,
+    #[msg("Mining power is already in full condition")]
+    NothingToRepair,
+    #[msg("Repairs in this currency are disabled")]
+    RepairUnavailable,
+    #[msg("Rig is already backed by an NFT")]
+    RigAlreadyTokenized,
+    #[msg("NFT-backed rigs move by transferring the NFT")]
+    RigIsTokenized,
+    #[msg("NFT-backed rig needs the holder's NFT token account")]
+    MissingRigNft,
+    #[msg("Accounts this proposal needs are missing")]
+    MissingProposalAccounts,
+    #[msg("Buyback wallet has not been set")]
+    BuybackWalletNotSet,
+    #[msg("Unpause everything before renouncing the authority")]
+    RenounceWhilePaused,
+    #[msg("Treasury account is required once a GPU token is set")]
+    MissingTreasury,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Bytes of a fully verified PriceUpdateV2 account
+    fn pyth_update_data(price: i64, conf: u64, exponent: i32, publish_time: i64) -> Vec<u8> {
+        let mut data = PYTH_PRICE_UPDATE_DISCRIMINATOR.to_vec();
+        data.extend_from_slice(&[0u8; 32]); // write_authority
+        data.push(1); // VerificationLevel::Full
+        data.extend_from_slice(&[0u8; 32]); // feed_id
+        data.extend_from_slice(&price.to_le_bytes());
+        data.extend_from_slice(&conf.to_le_bytes());
+        data.extend_from_slice(&exponent.to_le_bytes());
+        data.extend_from_slice(&publish_time.to_le_bytes());
+        data.extend_from_slice(&publish_time.to_le_bytes()); // prev_publish_time
+        data.extend_from_slice(&price.to_le_bytes()); // ema_price
+        data.extend_from_slice(&conf.to_le_bytes()); // ema_conf
+        data.extend_from_slice(&0u64.to_le_bytes()); // posted_slot
+        data
+    }
+
+    fn read_feed(owner: Pubkey, mut data: Vec<u8>, current_time: i64) -> Result<u64> {
+        let key = Pubkey::new_unique();
+        let mut lamports = 1_000_000;
+        let feed = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+        read_pyth_price(&feed, 60, 200, current_time)
+    }
+
+    #[test]
+    fn pyth_price_is_rescaled_to_8_decimals() {
+        // $150.12345678 at exponent -8 passes through unchanged
+        let data = pyth_update_data(15_012_345_678, 0, -8, 1_000);
+        assert_eq!(read_feed(PYTH_RECEIVER_PROGRAM_ID, data, 1_000).unwrap(), 15_012_345_678);
+        
+        // $150.12 at exponent -2 is scaled up
+        let data = pyth_update_data(15_012, 0, -2, 1_000);
+        assert_eq!(read_feed(PYTH_RECEIVER_PROGRAM_ID, data, 1_000).unwrap(), 15_012_000_000);
+        
+        // Extra precision below 8 decimals is truncated
+        let data = pyth_update_data(1_501_234_567_891, 0, -10, 1_000);
+        assert_eq!(read_feed(PYTH_RECEIVER_PROGRAM_ID, data, 1_000).unwrap(), 15_012_345_678);
+    }
+
+    #[test]
+    fn pyth_price_rejects_stale_updates() {
+        let data = pyth_update_data(15_000_000_000, 0, -8, 1_000);
+        assert!(read_feed(PYTH_RECEIVER_PROGRAM_ID, data.clone(), 1_060).is_ok());
+        assert_eq!(
+            read_feed(PYTH_RECEIVER_PROGRAM_ID, data, 1_061).unwrap_err(),
+            ErrorCode::StalePrice.into()
+        );
+    }
+
+    #[test]
+    fn pyth_price_rejects_wide_confidence() {
+        // 2% of $150 is $3
+        let data = pyth_update_data(15_000_000_000, 300_000_000, -8, 1_000);
+        assert!(read_feed(PYTH_RECEIVER_PROGRAM_ID, data, 1_000).is_ok());
+        
+        let data = pyth_update_data(15_000_000_000, 300_000_001, -8, 1_000);
+        assert_eq!(
+            read_feed(PYTH_RECEIVER_PROGRAM_ID, data, 1_000).unwrap_err(),
+            ErrorCode::PriceTooUncertain.into()
+        );
+    }
+
+    #[test]
+    fn pyth_price_rejects_foreign_owner() {
+        let data = pyth_update_data(15_000_000_000, 0, -8, 1_000);
+        assert_eq!(
+            read_feed(Pubkey::new_unique(), data, 1_000).unwrap_err(),
+            ErrorCode::InvalidPriceFeed.into()
+        );
+    }
+
+    fn zeroed_global_state() -> GlobalState {
+        GlobalState::deserialize(&mut &vec![0u8; GlobalState::INIT_SPACE][..]).unwrap()
+    }
+
+    fn zeroed_user_state() -> UserState {
+        UserState::deserialize(&mut &vec![0u8; UserState::INIT_SPACE][..]).unwrap()
+    }
+
+    #[test]
+    fn genesis_buy_then_sell_is_unprofitable() {
+        const RENT: u64 = 890_880;
+        const SEED: u64 = 10_000_000_000;
+        let amount = 1_000_000_000;
+        
+        let mut global_state = zeroed_global_state();
+        global_state.sol_usd_price = 150 * 100_000_000;
+        global_state.base_buy_rate = 1_000;
+        global_state.protocol_fee_val = 10;
+        global_state.genesis_mhs_threshold = DEFAULT_GENESIS_MHS_THRESHOLD;
+        global_state.genesis_tvl_threshold_usd = DEFAULT_GENESIS_TVL_THRESHOLD_USD;
+        global_state.protocol_seed_sol = SEED;
+        
+        // The first buy lands on an empty network and is priced at the flat genesis rate
+        let vault = SEED + RENT + amount;
+        let quote = quote_mhs(&global_state, PaymentCurrency::Sol, amount, vault, 0).unwrap();
+        assert_eq!(quote.fee_amount, 100_000_000);
+        assert_eq!(quote.mhs_after_fee, 900);
+        global_state.total_mining_power = quote.mhs_after_fee;
+        
+        // Selling straight back owns the whole network, but the seed is not for sale:
+        // the proceeds are the deposit, fee included, less the exit fee
+        let sell = quote_sell(&global_state, quote.mhs_after_fee, vault, 0, RENT).unwrap();
+        assert_eq!(sell.sol_payout + sell.sol_fee, amount);
+        assert_eq!(sell.sol_fee, amount / 10);
+        assert!(sell.sol_payout < amount);
+        
+        // A partial sell is a matching slice of the net TVL, whatever paid for the MH/s
+        let sell = quote_sell(&global_state, 300, vault, 0, RENT).unwrap();
+        assert_eq!(sell.sol_payout + sell.sol_fee, amount / 3);
+        
+        // GPU in the vault is shared the same way, even with SOL-only buyers
+        let sell = quote_sell(&global_state, 300, vault, 9_000_000, RENT).unwrap();
+        assert_eq!(sell.gpu_payout + sell.gpu_fee, 3_000_000);
+    }
+
+    #[test]
+    fn decay_factor_compounds_whole_days() {
+        assert_eq!(decay_factor(100, 0).unwrap(), DECAY_INDEX_ONE);
+        assert_eq!(decay_factor(100, 86_400).unwrap(), DECAY_INDEX_ONE / 100 * 99);
+        assert_eq!(decay_factor(100, 2 * 86_400).unwrap(), DECAY_INDEX_ONE / 10_000 * 9_801);
+        
+        // The part day wears linearly at the daily rate
+        assert_eq!(decay_factor(100, 43_200).unwrap(), DECAY_INDEX_ONE / 1_000 * 995);
+        assert_eq!(decay_factor(100, 86_400 + 43_200).unwrap(), DECAY_INDEX_ONE / 100_000 * 98_505);
+    }
+
+    #[test]
+    fn worn_totals_cover_every_holder() {
+        let mut global_state = zeroed_global_state();
+        global_state.decay_index = DECAY_INDEX_ONE;
+        global_state.decay_bps_per_day = 100;
+        global_state.last_decay_update = 1_000;
+        
+        let mut users: Vec<UserState> = [7, 1_001, 33_333, 999_999]
+            .into_iter()
+            .map(|mhs| {
+                let mut user_state = zeroed_user_state();
+                add_user_power(&mut user_state, mhs, mhs).unwrap();
+                user_state.decay_snapshot = DECAY_INDEX_ONE;
+                user_state
+            })
+            .collect();
+        let mut owner = zeroed_user_state();
+        let mut position = LockedPosition::deserialize(&mut &vec![0u8; LockedPosition::INIT_SPACE][..]).unwrap();
+        position.mining_power = 12_345;
+        position.reward_weight = 15_431;
+        position.decay_snapshot = DECAY_INDEX_ONE;
+        let mut rig = RigPosition::deserialize(&mut &vec![0u8; RigPosition::INIT_SPACE][..]).unwrap();
+        rig.mining_power = 4_321;
+        rig.decay_snapshot = DECAY_INDEX_ONE;
+        
+        global_state.total_mining_power = users.iter().map(|u| u.mining_power).sum::<u64>() + 12_345 + 4_321;
+        global_state.total_locked_mhs = 12_345;
+        global_state.total_lock_bonus = 15_431 - 12_345;
+        global_state.total_rig_mhs = 4_321;
+        
+        let mut now = global_state.last_decay_update;
+        for (step, elapsed) in [1, 59, 3_600, 43_201, 86_400, 7 * 86_400 + 13, 90 * 86_400].into_iter().enumerate() {
+            now += elapsed;
+            apply_decay(&mut global_state, now).unwrap();
+            
+            // Holders are only worn when touched, so settle some of them each step
+            for (i, user_state) in users.iter_mut().enumerate() {
+                if (i + step) % 2 == 0 {
+                    settle_user(user_state, &global_state, now).unwrap();
+                }
+            }
+            if step % 2 == 0 {
+                settle_position(&mut position, &mut owner, &global_state, now).unwrap();
+                settle_rig(&mut rig, &global_state, now).unwrap();
+            }
+            
+            let index = global_state.decay_index;
+            let worn = |power, snapshot| worn_power(power, snapshot, index).unwrap();
+            let position_mhs = worn(position.mining_power, position.decay_snapshot);
+            let position_bonus = worn(position.reward_weight - position.mining_power, position.decay_snapshot);
+            let rig_mhs = worn(rig.mining_power, rig.decay_snapshot);
+            let holders = users.iter().map(|u| worn(u.mining_power, u.decay_snapshot)).sum::<u64>()
+                + position_mhs
+                + rig_mhs;
+            
+            assert!(global_state.total_mining_power >= holders);
+            assert!(global_state.total_locked_mhs >= position_mhs);
+            assert!(global_state.total_lock_bonus >= position_bonus);
+            assert!(global_state.total_rig_mhs >= rig_mhs);
+            
+            // ...while only drifting from them by rounding: under 1 MH/s per step for
+            // the total and per settlement for each of the six holders
+            assert!(global_state.total_mining_power - holders <= 7 * (step as u64 + 1));
+        }
+        
+        // 90 days at 1% a day leaves well under half
+        assert!(global_state.total_mining_power < 1_051_006 * 2 / 5);
+    }
+    
+    #[test]
+    fn emission_integrates_across_epochs() {
+        let day = 86_400i64;
+        let vault = 1_000_000_000u64;
+        let mut global_state = zeroed_global_state();
+        global_state.daily_pool_percentage = 10;
+        
+        // No schedule: two days at 10%
+        assert_eq!(calculate_earnings(&global_state, 10 * day, 12 * day, vault).unwrap(), 200_000_000);
+        
+        // Halving daily from day 10: 0.5 × 10% + 5% + 0.5 × 2.5%
+        global_state.emission_start = 10 * day;
+        global_state.emission_epoch_secs = day as u32;
+        global_state.emission_decay_bps = 5_000;
+        assert_eq!(calculate_earnings(&global_state, 10 * day + day / 2, 12 * day + day / 2, vault).unwrap(), 112_500_000);
+        
+        // Splitting the claim at any point pays the same
+        let split = calculate_earnings(&global_state, 10 * day + day / 2, 11 * day + day / 4, vault).unwrap()
+            + calculate_earnings(&global_state, 11 * day + day / 4, 12 * day + day / 2, vault).unwrap();
+        assert_eq!(split, 112_500_000);
+        
+        // The part before the start pays the base rate: 0.5 × 10% + 10% + 0.5 × 5%
+        global_state.emission_start = 11 * day;
+        assert_eq!(calculate_earnings(&global_state, 10 * day + day / 2, 12 * day + day / 2, vault).unwrap(), 175_000_000);
+        
+        // Keeping every bps is a flat rate
+        global_state.emission_decay_bps = 10_000;
+        assert_eq!(calculate_earnings(&global_state, 10 * day, 13 * day, vault).unwrap(), 300_000_000);
+        
+        // The most extreme schedule still integrates a century without overflow
+        global_state.daily_pool_percentage = MAX_DAILY_POOL_PERCENTAGE;
+        global_state.emission_epoch_secs = MAX_EMISSION_EPOCH_SECS;
+        for decay_bps in [1, 9_999, 10_000] {
+            global_state.emission_decay_bps = decay_bps;
+            calculate_earnings(&global_state, 0, 11 * day + 36_500 * day, u64::MAX).unwrap();
+        }
+    }
+    
+    #[test]
+    fn gpu_penalty_discounts_the_deposit() {
+        let mut global_state = zeroed_global_state();
+        global_state.sol_usd_price = 150 * 100_000_000;
+        global_state.gpu_usd_price = 150_000_000;
+        global_state.base_buy_rate = 1_000;
+        global_state.gpu_penalty_bps = 1_500;
+        
+        // $150 either way, but the GPU deposit is priced 15% lower
+        let sol = quote_mhs(&global_state, PaymentCurrency::Sol, 1_000_000_000, 0, 0).unwrap();
+        let gpu = quote_mhs(&global_state, PaymentCurrency::Gpu, 100_000_000, 0, 0).unwrap();
+        assert_eq!(sol.usd_value, gpu.usd_value);
+        assert_eq!(gpu.penalty_usd, gpu.usd_value / 100 * 15);
+        assert_eq!(sol.mhs_after_fee, 1_000);
+        assert_eq!(gpu.mhs_after_fee, 850);
+    }
+    
+    #[test]
+    fn price_moves_are_capped_per_window() {
+        let mut global_state = zeroed_global_state();
+        global_state.max_price_move_bps = DEFAULT_MAX_PRICE_MOVE_BPS;
+        global_state.sol_usd_price = 100 * 100_000_000;
+        global_state.gpu_usd_price = 100_000_000;
+        let now = 1_000_000;
+        
+        // The first update in a window may move the full 20%
+        check_manual_prices(&mut global_state, 120 * 100_000_000, 120_000_000, now).unwrap();
+        global_state.sol_usd_price = 120 * 100_000_000;
+        global_state.gpu_usd_price = 120_000_000;
+        
+        // A second update straight after is still measured from the window start
+        assert!(check_manual_prices(&mut global_state, 140 * 100_000_000, 120_000_000, now).is_err());
+        assert!(check_manual_prices(&mut global_state, 120 * 100_000_000, 121_000_000, now + 60).is_err());
+        check_manual_prices(&mut global_state, 90 * 100_000_000, 110_000_000, now + 60).unwrap();
+        
+        // Once the window runs out the current prices become the reference
+        check_manual_prices(&mut global_state, 140 * 100_000_000, 140_000_000, now + PRICE_MOVE_WINDOW_SECS).unwrap();
+        assert_eq!(global_state.window_sol_usd_price, 120 * 100_000_000);
+    }
+    
+    #[test]
+    fn sol_buy_needs_no_gpu_accounts_before_gpu_token_is_set() {
+        let mut global_state = zeroed_global_state();
+        global_state.sol_usd_price = 150 * 100_000_000;
+        global_state.base_buy_rate = 1_000;
+        global_state.protocol_fee_val = 10;
+        
+        // No GPU vault, treasury or GPU price yet: the buy prices on SOL alone
+        let gpu_vault_balance = optional_gpu_vault_balance(&global_state, None).unwrap();
+        assert_eq!(gpu_vault_balance, 0);
+        assert!(optional_treasury(&global_state, None).unwrap().is_none());
+        let quote = quote_mhs(&global_state, PaymentCurrency::Sol, 1_000_000_000, 1_000_000_000, gpu_vault_balance).unwrap();
+        assert_eq!(quote.mhs_after_fee, 900);
+        
+        // Once a GPU token is set both accounts must be passed
+        global_state.gpu_token_mint = Pubkey::new_unique();
+        assert!(optional_gpu_vault_balance(&global_state, None).is_err());
+        assert!(optional_treasury(&global_state, None).is_err());
+    }
+    
+    #[test]
+    fn difficulty_compounds_target_growth_over_missed_epochs() {
+        let day = 86_400i64;
+        let mut global_state = zeroed_global_state();
+        global_state.decay_index = DECAY_INDEX_ONE;
+        global_state.difficulty = 2 * HASH_PER_MHS;
+        global_state.difficulty_epoch_secs = day as u32;
+        global_state.target_growth_bps = 1_000;
+        global_state.epoch_start_mining_power = 1_000;
+        global_state.total_mining_power = 1_210;
+        
+        // Nothing moves before the epoch ends
+        retarget_difficulty(&mut global_state, day - 1).unwrap();
+        assert_eq!(global_state.difficulty, 2 * HASH_PER_MHS);
+        
+        // 21% over two epochs is exactly 10% an epoch compounded, so the difficulty holds
+        retarget_difficulty(&mut global_state, 2 * day + 5).unwrap();
+        assert_eq!(global_state.difficulty, 2 * HASH_PER_MHS);
+        assert_eq!(global_state.difficulty_epoch_start, 2 * day);
+        assert_eq!(global_state.epoch_start_mining_power, 1_210);
+        
+        // Three flat epochs miss a 1.1^3 target and ease it in proportion
+        retarget_difficulty(&mut global_state, 5 * day).unwrap();
+        assert_eq!(global_state.difficulty, 2 * HASH_PER_MHS * 1_210 / 1_610);
+        assert_eq!(global_state.difficulty_epoch_start, 5 * day);
+        
+        // A target too large to represent only drops it to the floor
+        retarget_difficulty(&mut global_state, 10_005 * day).unwrap();
+        assert_eq!(global_state.difficulty, HASH_PER_MHS);
+        assert_eq!(global_state.difficulty_epoch_start, 10_005 * day);
+    }
+    
+    #[test]
+    fn fee_split_routes_rounding_dust_to_the_last_recipient() {
+        let mut global_state = zeroed_global_state();
+        let shares = [3_333, 3_333, 3_334].map(|bps| FeeShare { recipient: Pubkey::new_unique(), bps });
+        global_state.fee_split[..3].copy_from_slice(&shares);
+        global_state.fee_split_count = 3;
+        
+        // Every recipient but the last is rounded down; the last takes what's left
+        assert_eq!(fee_split_amounts(&global_state, 1_000_001).unwrap(), vec![333_300, 333_300, 333_401]);
+        assert_eq!(fee_split_amounts(&global_state, 2).unwrap(), vec![0, 0, 2]);
+        assert_eq!(fee_split_amounts(&global_state, 0).unwrap(), vec![0, 0, 0]);
+        
+        // Buy fees only leave the vault once a split exists, net of referral rewards
+        global_state.protocol_fee_val = 10;
+        assert_eq!(routed_buy_fee(&global_state, 1_000, 30).unwrap(), 70);
+        assert_eq!(routed_buy_fee(&global_state, 1_000, 150).unwrap(), 0);
+        global_state.fee_split_count = 0;
+        assert_eq!(routed_buy_fee(&global_state, 1_000, 30).unwrap(), 0);
+        
+        // A split must cover exactly 10,000 bps across real recipients
+        let propose = |shares: Vec<FeeShare>| validate_admin_action(&AdminAction::SetFeeSplit { shares }, 0);
+        assert!(propose(shares.to_vec()).is_ok());
+        assert!(propose(shares[..2].to_vec()).is_err());
+        assert!(propose(Vec::new()).is_err());
+        let mut unset = shares.to_vec();
+        unset[1].recipient = Pubkey::default();
+        assert!(propose(unset).is_err());
+    }
+    
+    #[test]
+    fn lock_weight_follows_the_term_multiplier() {
+        assert_eq!(LockTerm::Days30.reward_weight(1_000).unwrap(), 1_100);
+        assert_eq!(LockTerm::Days90.reward_weight(1_000).unwrap(), 1_250);
+        assert_eq!(LockTerm::Days180.reward_weight(1_000).unwrap(), 1_500);
+        assert_eq!(LockTerm::Days180.duration_secs(), 180 * 86_400);
+        
+        // Rounding only ever trims the bonus, never the locked MH/s itself
+        assert_eq!(LockTerm::Days30.reward_weight(9).unwrap(), 9);
+        assert_eq!(LockTerm::Days90.reward_weight(7).unwrap(), 8);
+        assert!(LockTerm::Days180.reward_weight(u64::MAX).is_err());
+        
+        // Locked MH/s already sits in total_mining_power; only the bonus is added
+        let mut global_state = zeroed_global_state();
+        global_state.total_mining_power = 3_000;
+        global_state.total_locked_mhs = 1_000;
+        global_state.total_lock_bonus = LockTerm::Days90.reward_weight(1_000).unwrap() - 1_000;
+        assert_eq!(reward_weight(&global_state).unwrap(), 3_250);
+    }
+    
+    #[test]
+    fn pause_bits_gate_only_their_own_actions() {
+        let mut global_state = zeroed_global_state();
+        assert!(require_not_paused(&global_state, PAUSE_ALL).is_ok());
+        
+        // Pausing sells leaves buys and transfers running
+        global_state.paused = PAUSE_SELLS;
+        assert_eq!(require_not_paused(&global_state, PAUSE_SELLS).unwrap_err(), ErrorCode::Paused.into());
+        assert!(require_not_paused(&global_state, PAUSE_SOL_BUYS).is_ok());
+        assert!(require_not_paused(&global_state, PAUSE_TRANSFERS).is_ok());
+        
+        // A check over several bits fails if any of them is set
+        assert!(require_not_paused(&global_state, PAUSE_TRANSFERS | PAUSE_SELLS).is_err());
+        
+        // PAUSE_ALL is every bit, so it stops each action
+        global_state.paused = PAUSE_ALL;
+        assert_eq!(PAUSE_ALL, u8::MAX);
+        for bit in 0..8 {
+            assert!(require_not_paused(&global_state, 1 << bit).is_err());
+        }
+    }
+    
+    // Serialized user_state account of `owner`, referred by `referrer`
+    fn referrer_account_data(owner: Pubkey, referrer: Option<Pubkey>) -> Vec<u8> {
+        let mut user_state = zeroed_user_state();
+        user_state.owner = owner;
+        user_state.referrer = referrer;
+        user_state.decay_snapshot = DECAY_INDEX_ONE;
+        let mut data = Vec::new();
+        user_state.try_serialize(&mut data).unwrap();
+        data.resize(8 + UserState::INIT_SPACE, 0);
+        data
+    }
+    
+    fn user_state_key(owner: Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[b"user_state", owner.as_ref()], &crate::ID).0
+    }
+    
+    #[test]
+    fn referral_chain_pays_each_level_once() {
+        let mut global_state = zeroed_global_state();
+        global_state.decay_index = DECAY_INDEX_ONE;
+        global_state.referral_levels = 4;
+        global_state.referral_bps[..4].copy_from_slice(&[500, 300, 200, 100]);
+        global_state.total_mining_power = 10_000;
+        
+        // buyer -> direct -> second -> third -> buyer
+        let owners = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        let keys = [1, 2, 3, 0].map(|i| user_state_key(owners[i]));
+        let mut lamports = [1_000_000u64; 4];
+        let mut data: Vec<Vec<u8>> = [1, 2, 3, 0]
+            .into_iter()
+            .map(|i| referrer_account_data(owners[i], Some(owners[(i + 1) % 4])))
+            .collect();
+        let accounts: Vec<AccountInfo> = keys
+            .iter()
+            .zip(lamports.iter_mut())
+            .zip(data.iter_mut())
+            .map(|((key, lamports), data)| AccountInfo::new(key, false, true, lamports, data, &crate::ID, false, 0))
+            .collect();
+        
+        let mut buyer = zeroed_user_state();
+        buyer.owner = owners[0];
+        let mut direct = Account::<UserState>::try_from(&accounts[0]).unwrap();
+        let purchase = ReferralPurchase { currency: PaymentCurrency::Sol, amount: 1_000_000_000, mhs_bought: 10_000 };
+        let (bonus, reward) = settle_referral(
+            &mut global_state,
+            &mut buyer,
+            Some(owners[1]),
+            Some(&mut direct),
+            &accounts[1..],
+            purchase,
+            0,
+        ).unwrap();
+        
+        // Each level gets its own bps of the MH/s bought; the walk stops when it
+        // comes back round to the buyer, so the fourth level pays nothing
+        assert_eq!((bonus, reward), (1_000, 0));
+        assert_eq!(buyer.referrer, Some(owners[1]));
+        assert_eq!(direct.mining_power, 500);
+        let upline: Vec<u64> = accounts[1..]
+            .iter()
+            .map(|info| Account::<UserState>::try_from(info).unwrap().mining_power)
+            .collect();
+        assert_eq!(upline, vec![300, 200, 0]);
+        assert_eq!(global_state.total_mining_power, 11_000);
+        
+        // Upline accounts must come in chain order
+        let mut direct = Account::<UserState>::try_from(&accounts[0]).unwrap();
+        let swapped = [accounts[2].clone(), accounts[1].clone()];
+        let purchase = ReferralPurchase { currency: PaymentCurrency::Sol, amount: 1_000_000_000, mhs_bought: 10_000 };
+        assert_eq!(
+            settle_referral(&mut global_state, &mut buyer, None, Some(&mut direct), &swapped, purchase, 0).unwrap_err(),
+            ErrorCode::InvalidReferrer.into()
+        );
+        
+        // A schedule may not pay out more than MAX_REFERRAL_TOTAL_BPS in total
+        let propose = |level_bps: Vec<u16>| validate_admin_action(&AdminAction::SetReferralSchedule { level_bps }, 0);
+        assert!(propose(vec![1_000, 600, 400]).is_ok());
+        assert!(propose(vec![1_000, 600, 401]).is_err());
+        assert!(propose(vec![100; MAX_REFERRAL_LEVELS + 1]).is_err());
+    }
+    
+    #[test]
+    fn fee_referrals_are_capped_by_the_buy_fee() {
+        let mut global_state = zeroed_global_state();
+        global_state.referral_reward_mode = ReferralRewardMode::Fees;
+        global_state.referral_levels = 3;
+        global_state.referral_bps[..3].copy_from_slice(&[500, 300, 200]);
+        global_state.protocol_fee_val = 6;
+        
+        let buyer = Pubkey::new_unique();
+        let mut referrers: Vec<UserState> = (0..3).map(|_| zeroed_user_state()).collect();
+        let purchase = ReferralPurchase { currency: PaymentCurrency::Gpu, amount: 1_000_000, mhs_bought: 10_000 };
+        let (mut fee_budget, _) = split_protocol_fee(purchase.amount, global_state.protocol_fee_val).unwrap();
+        let paid: Vec<(u64, u64)> = referrers
+            .iter_mut()
+            .enumerate()
+            .map(|(level, referrer)| {
+                pay_referral(&mut global_state, referrer, buyer, level, &purchase, &mut fee_budget, 0).unwrap()
+            })
+            .collect();
+        
+        // 5% and 3% fit in the 6% fee only as 5% and 1%; the third level gets nothing
+        assert_eq!(paid, vec![(0, 50_000), (0, 10_000), (0, 0)]);
+        assert_eq!(fee_budget, 0);
+        
+        // Rewards are owed in the deposit currency and reserved, no MH/s is minted
+        assert_eq!(referrers[0].referral_gpu_earned, 50_000);
+        assert_eq!(referrers[0].referral_sol_earned, 0);
+        assert_eq!(referrers[0].mining_power, 0);
+        assert_eq!(global_state.total_unclaimed_gpu, 60_000);
+        assert_eq!(global_state.total_mining_power, 0);
+        
+        // Only the part of the fee referrals didn't take leaves the vault
+        global_state.fee_split_count = 1;
+        assert_eq!(routed_buy_fee(&global_state, purchase.amount, 60_000).unwrap(), 0);
+        assert_eq!(routed_buy_fee(&global_state, purchase.amount, 50_000).unwrap(), 10_000);
+    }
+    
+    #[test]
+    fn transfer_fee_is_burned_from_the_amount_sent() {
+        let mut global_state = zeroed_global_state();
+        global_state.total_mining_power = 10_000;
+        global_state.transfer_fee_bps = 250;
+        let mut sender = zeroed_user_state();
+        add_user_power(&mut sender, 800, 1_000).unwrap();
+        let mut recipient = zeroed_user_state();
+        
+        // 2.5% of 400 is burned; the rest arrives carrying the sender's wear
+        assert_eq!(transfer_user_power(&mut sender, &mut recipient, &mut global_state, 400).unwrap(), (10, 390));
+        assert_eq!((sender.mining_power, sender.rated_mining_power), (400, 500));
+        assert_eq!((recipient.mining_power, recipient.rated_mining_power), (390, 487));
+        assert_eq!(global_state.total_mining_power, 9_990);
+        
+        // Without a fee the whole amount arrives
+        global_state.transfer_fee_bps = 0;
+        assert_eq!(transfer_user_power(&mut sender, &mut recipient, &mut global_state, 400).unwrap(), (0, 400));
+        assert_eq!(sender.mining_power, 0);
+        assert_eq!(global_state.total_mining_power, 9_990);
+        
+        let propose = |transfer_fee_bps| validate_admin_action(&AdminAction::SetTransferFee { transfer_fee_bps }, 0);
+        assert!(propose(MAX_TRANSFER_FEE_BPS).is_ok());
+        assert!(propose(MAX_TRANSFER_FEE_BPS + 1).is_err());
+    }
+    
+    fn nft_account(mint: Pubkey, owner: Pubkey, amount: u64) -> TokenAccount {
+        use anchor_lang::solana_program::program_pack::Pack;
+        let account = token::spl_token::state::Account {
+            mint,
+            owner,
+            amount,
+            state: token::spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        };
+        let mut data = vec![0u8; token::spl_token::state::Account::LEN];
+        account.pack_into_slice(&mut data);
+        TokenAccount::try_deserialize(&mut &data[..]).unwrap()
+    }
+    
+    #[test]
+    fn rig_rewards_follow_the_rig_to_each_holder() {
+        let mut global_state = zeroed_global_state();
+        global_state.decay_index = DECAY_INDEX_ONE;
+        let (seller, buyer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut rig = RigPosition::deserialize(&mut &vec![0u8; RigPosition::INIT_SPACE][..]).unwrap();
+        rig.owner = seller;
+        rig.mining_power = 1_000;
+        rig.rated_mining_power = 1_000;
+        rig.decay_snapshot = DECAY_INDEX_ONE;
+        
+        // Everything the rig earned before a transfer is banked to the seller
+        global_state.acc_sol_per_mhs = 3 * ACC_PRECISION;
+        settle_rig(&mut rig, &global_state, 100).unwrap();
+        let mut seller_state = zeroed_user_state();
+        move_rig_ledgers(&mut rig, &mut seller_state, true).unwrap();
+        rig.owner = buyer;
+        assert_eq!((seller_state.unclaimed_sol, seller_state.unclaimed_hash), (3_000, 100_000));
+        assert_eq!((rig.unclaimed_sol, rig.unclaimed_hash), (0, 0));
+        
+        // ...and only what it earns afterwards reaches the buyer; a harvest leaves
+        // the hash on the rig for compound_rig
+        global_state.acc_sol_per_mhs = 5 * ACC_PRECISION;
+        settle_rig(&mut rig, &global_state, 150).unwrap();
+        let mut buyer_state = zeroed_user_state();
+        move_rig_ledgers(&mut rig, &mut buyer_state, false).unwrap();
+        assert_eq!((buyer_state.unclaimed_sol, buyer_state.unclaimed_hash), (2_000, 0));
+        assert_eq!(rig.unclaimed_hash, 50_000);
+        
+        // A plain rig answers to its recorded owner only
+        assert_eq!(authorize_rig_holder(&mut rig, seller, None).unwrap_err(), ErrorCode::Unauthorized.into());
+        authorize_rig_holder(&mut rig, buyer, None).unwrap();
+        
+        // A tokenized rig answers to whoever holds its NFT
+        let (nft_mint, holder) = (Pubkey::new_unique(), Pubkey::new_unique());
+        rig.nft_mint = Some(nft_mint);
+        assert_eq!(authorize_rig_holder(&mut rig, holder, None).unwrap_err(), ErrorCode::MissingRigNft.into());
+        let sold = nft_account(nft_mint, holder, 0);
+        assert_eq!(authorize_rig_holder(&mut rig, holder, Some(&sold)).unwrap_err(), ErrorCode::Unauthorized.into());
+        let other = nft_account(Pubkey::new_unique(), holder, 1);
+        assert_eq!(authorize_rig_holder(&mut rig, holder, Some(&other)).unwrap_err(), ErrorCode::Unauthorized.into());
+        authorize_rig_holder(&mut rig, holder, Some(&nft_account(nft_mint, holder, 1))).unwrap();
+        assert_eq!(rig.owner, holder);
+    }
+    
+    #[test]
+    fn timelock_delay_stays_within_bounds() {
+        let day = 24 * 60 * 60;
+        let mut global_state = zeroed_global_state();
+        assert_eq!(timelock_delay(&global_state), DEFAULT_TIMELOCK_DELAY_SECS);
+        global_state.timelock_delay_secs = 3 * day;
+        assert_eq!(timelock_delay(&global_state), 3 * day);
+        
+        // Out-of-range values left by older deployments are clamped, new ones rejected
+        global_state.timelock_delay_secs = 60;
+        assert_eq!(timelock_delay(&global_state), MIN_TIMELOCK_DELAY_SECS);
+        global_state.timelock_delay_secs = u32::MAX;
+        assert_eq!(timelock_delay(&global_state), MAX_TIMELOCK_DELAY_SECS);
+        let propose = |delay_secs| validate_admin_action(&AdminAction::SetTimelockDelay { delay_secs }, 0);
+        assert!(propose(MIN_TIMELOCK_DELAY_SECS).is_ok());
+        assert!(propose(MAX_TIMELOCK_DELAY_SECS).is_ok());
+        assert!(propose(MIN_TIMELOCK_DELAY_SECS - 1).is_err());
+        assert!(propose(MAX_TIMELOCK_DELAY_SECS + 1).is_err());
+        
+        // Proposals that could never execute are turned away up front
+        assert!(validate_admin_action(&AdminAction::DrainVault { amount: 0 }, 0).is_err());
+        assert!(validate_admin_action(&AdminAction::DrainVault { amount: 1 }, 0).is_ok());
+    }
+    
+    #[test]
+    fn pyth_feeds_refresh_buy_prices() {
+        let mut global_state = zeroed_global_state();
+        global_state.price_source = PriceSource::Pyth;
+        global_state.max_price_age_secs = 60;
+        global_state.max_confidence_bps = 200;
+        let clock = Clock { slot: 42, unix_timestamp: 1_000, ..Clock::default() };
+        
+        // Mock price accounts, as a local validator would load them
+        let (sol_key, gpu_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (mut sol_lamports, mut gpu_lamports) = (1_000_000, 1_000_000);
+        let mut sol_data = pyth_update_data(15_000_000_000, 0, -8, 990);
+        let mut gpu_data = pyth_update_data(150_000_000, 0, -8, 995);
+        let sol_feed = AccountInfo::new(&sol_key, false, false, &mut sol_lamports, &mut sol_data, &PYTH_RECEIVER_PROGRAM_ID, false, 0);
+        let gpu_feed = AccountInfo::new(&gpu_key, false, false, &mut gpu_lamports, &mut gpu_data, &PYTH_RECEIVER_PROGRAM_ID, false, 0);
+        
+        // A SOL buy against an empty GPU vault reads SOL alone and stamps nothing
+        refresh_prices(&mut global_state, Some(&sol_feed), None, PaymentCurrency::Sol, 0, &clock).unwrap();
+        assert_eq!(global_state.sol_usd_price, 15_000_000_000);
+        assert_eq!((global_state.price_updated_slot, global_state.price_updated_at), (0, 0));
+        
+        // GPU in the vault counts towards TVL, so its feed is required
+        assert_eq!(
+            refresh_prices(&mut global_state, Some(&sol_feed), None, PaymentCurrency::Sol, 1, &clock).unwrap_err(),
+            ErrorCode::MissingPriceFeed.into()
+        );
+        refresh_prices(&mut global_state, Some(&sol_feed), Some(&gpu_feed), PaymentCurrency::Sol, 1, &clock).unwrap();
+        assert_eq!(global_state.gpu_usd_price, 150_000_000);
+        assert_eq!((global_state.price_updated_slot, global_state.price_updated_at), (42, 1_000));
+        
+        // Manual mode ignores the feeds and only checks the age of the last update
+        global_state.price_source = PriceSource::Manual;
+        global_state.manual_price_max_age_secs = 60;
+        refresh_prices(&mut global_state, None, None, PaymentCurrency::Gpu, 1, &clock).unwrap();
+        let later = Clock { unix_timestamp: 1_061, ..clock };
+        assert_eq!(
+            refresh_prices(&mut global_state, None, None, PaymentCurrency::Gpu, 1, &later).unwrap_err(),
+            ErrorCode::StalePrice.into()
+        );
+    }
+    
+    #[test]
+    fn legacy_user_state_reads_after_migration() {
+        let (owner, referrer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        for referrer in [Some(referrer), None] {
+            // The pre-ledger-split layout, in an account sized for it
+            let mut data = UserState::DISCRIMINATOR.to_vec();
+            data.extend_from_slice(owner.as_ref());
+            data.extend_from_slice(&1_000u64.to_le_bytes()); // mining_power
+            data.extend_from_slice(&20u64.to_le_bytes()); // unclaimed_earnings
+            data.extend_from_slice(&30u64.to_le_bytes()); // unclaimed_gpu_earnings
+            data.extend_from_slice(&1_234i64.to_le_bytes()); // last_claim
+            referrer.serialize(&mut data).unwrap();
+            data.extend_from_slice(&40u64.to_le_bytes()); // total_sol_claimed
+            data.extend_from_slice(&50u64.to_le_bytes()); // total_gpu_claimed
+            data.resize(8 + 32 + 8 * 4 + 33 + 8 * 2, 0);
+            
+            // migrate_user_state zero-fills the grown tail, which reads as the legacy defaults
+            data.resize(8 + UserState::INIT_SPACE, 0);
+            let user_state = UserState::try_deserialize(&mut &data[..]).unwrap();
+            assert_eq!((user_state.owner, user_state.referrer), (owner, referrer));
+            assert_eq!((user_state.mining_power, user_state.unclaimed_sol, user_state.unclaimed_gpu), (1_000, 20, 30));
+            assert_eq!((user_state.last_claim, user_state.total_sol_claimed, user_state.total_gpu_claimed), (1_234, 40, 50));
+            assert_eq!((user_state.unclaimed_hash, user_state.last_hash_update), (0, 0));
+            assert_eq!((user_state.sol_reward_debt, user_state.rated_mining_power, user_state.decay_snapshot), (0, 0, 0));
+        }
+    }
 }